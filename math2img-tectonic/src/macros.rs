@@ -0,0 +1,438 @@
+/// User-defined macros (`\newcommand`, `\renewcommand`, `\providecommand`,
+/// `\def`, `\DeclareMathOperator`), collected from a document and expanded in
+/// its equations before they are parsed.
+use std::collections::HashMap;
+use std::fmt;
+
+/// Expansion stops after this many nested macro calls, which catches
+/// self-referencing definitions such as `\def\a{\a}`.
+const MAX_DEPTH: usize = 64;
+
+/// Expansion stops after this many macro calls in one snippet, which catches
+/// definitions that grow without nesting deeply, such as `\def\a{\a\a}`.
+const MAX_EXPANSIONS: usize = 1_000;
+
+/// Macros that hold layout parameters rather than math. Their definitions
+/// inside an equation are left in place for the parser (or LaTeX) to apply.
+const PARAMETERS: &[&str] = &["arraystretch"];
+
+/// A macro definition: number of parameters, an optional default value for
+/// `#1` (which makes it an `[optional]` argument), and the replacement text.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub params: usize,
+    pub default: Option<String>,
+    pub body: String,
+}
+
+/// Expansion ran past `MAX_DEPTH` or `MAX_EXPANSIONS`. Macro calls beyond
+/// the limit are dropped from the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionLimitExceeded;
+
+impl fmt::Display for ExpansionLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("macro expansion limit exceeded")
+    }
+}
+
+impl std::error::Error for ExpansionLimitExceeded {}
+
+/// Macro calls an expansion may still make, and whether it ran out.
+struct Budget {
+    calls: usize,
+    exceeded: bool,
+}
+
+/// Macros known to a document, keyed by name without the backslash.
+#[derive(Debug, Clone, Default)]
+pub struct MacroTable {
+    macros: HashMap<String, Macro>,
+}
+
+impl MacroTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect every macro definition in a document, preamble and body alike.
+    /// Later definitions replace earlier ones, except for `\providecommand`.
+    pub fn from_document(content: &str) -> Self {
+        let mut table = MacroTable::new();
+        let chars: Vec<char> = content.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '%' {
+                i = skip_comment(&chars, i);
+                continue;
+            }
+            if chars[i] == '\\' {
+                if let Some(def) = parse_definition(&chars, i) {
+                    table.add(def.name, def.provide, def.mac);
+                    i = def.end;
+                    continue;
+                }
+                i += 2;
+                continue;
+            }
+            i += 1;
+        }
+        table
+    }
+
+    /// Number of distinct macros defined.
+    pub fn len(&self) -> usize {
+        self.macros.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    fn add(&mut self, name: String, provide: bool, mac: Macro) {
+        if PARAMETERS.contains(&name.as_str()) || (provide && self.macros.contains_key(&name)) {
+            return;
+        }
+        self.macros.insert(name, mac);
+    }
+
+    /// Expand known macros in a math snippet. Definitions inside the snippet
+    /// are applied from that point on and removed from the output, apart
+    /// from layout parameters such as `\arraystretch`. Runaway definitions
+    /// stop at the expansion limit, which is reported alongside the output.
+    pub fn expand(&self, input: &str) -> (String, Option<ExpansionLimitExceeded>) {
+        let mut local = self.clone();
+        let chars: Vec<char> = input.chars().collect();
+        let mut out = String::with_capacity(input.len());
+        let mut budget = Budget { calls: MAX_EXPANSIONS, exceeded: false };
+        local.expand_into(&chars, &mut out, 0, &mut budget);
+        (out, budget.exceeded.then_some(ExpansionLimitExceeded))
+    }
+
+    /// Expand `chars` into `out`. Once the budget is spent, or calls nest
+    /// deeper than `MAX_DEPTH`, the remaining calls are dropped.
+    fn expand_into(&mut self, chars: &[char], out: &mut String, depth: usize, budget: &mut Budget) {
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '\\' {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            if let Some(def) = parse_definition(chars, i) {
+                if PARAMETERS.contains(&def.name.as_str()) {
+                    out.extend(&chars[i..def.end]);
+                }
+                self.add(def.name, def.provide, def.mac);
+                i = def.end;
+                continue;
+            }
+            let (name, end) = read_name(chars, i + 1);
+            let Some(mac) = self.macros.get(&name).cloned() else {
+                out.push('\\');
+                out.push_str(&name);
+                i = end;
+                continue;
+            };
+            if depth >= MAX_DEPTH || budget.calls == 0 {
+                budget.calls = 0;
+                budget.exceeded = true;
+            } else {
+                budget.calls -= 1;
+            }
+
+            let mut pos = end;
+            let mut args = Vec::with_capacity(mac.params);
+            if let Some(default) = &mac.default {
+                let (arg, next) = read_optional(chars, pos).unwrap_or((default.clone(), pos));
+                args.push(arg);
+                pos = next;
+            }
+            while args.len() < mac.params {
+                let (arg, next) = read_argument(chars, pos);
+                args.push(arg);
+                pos = next;
+            }
+
+            if budget.exceeded {
+                i = pos;
+                continue;
+            }
+
+            let body: Vec<char> = substitute(&mac.body, &args).chars().collect();
+            self.expand_into(&body, out, depth + 1, budget);
+            // Keep a control word from running into the letters that follow it.
+            if out.ends_with(|c: char| c.is_ascii_alphabetic())
+                && chars.get(pos).is_some_and(|c| c.is_ascii_alphabetic())
+                && ends_with_control_word(out)
+            {
+                out.push(' ');
+            }
+            i = pos;
+        }
+    }
+}
+
+/// A definition found at some position in the source.
+struct Definition {
+    name: String,
+    mac: Macro,
+    /// `\providecommand` keeps an existing definition.
+    provide: bool,
+    /// Index just past the definition.
+    end: usize,
+}
+
+/// Parse a macro definition starting at the backslash at `start`.
+fn parse_definition(chars: &[char], start: usize) -> Option<Definition> {
+    let (cmd, mut pos) = read_name(chars, start + 1);
+    match cmd.as_str() {
+        "newcommand" | "renewcommand" | "providecommand" => {
+            pos = skip_star(chars, pos);
+            let (name, next) = read_macro_name(chars, pos)?;
+            pos = next;
+            let mut params = 0;
+            if let Some((n, next)) = read_optional(chars, pos) {
+                params = n.trim().parse().ok()?;
+                pos = next;
+            }
+            let mut default = None;
+            if let Some((d, next)) = read_optional(chars, pos) {
+                default = Some(d);
+                pos = next;
+            }
+            let (body, end) = read_group(chars, pos)?;
+            Some(Definition {
+                name,
+                mac: Macro { params, default, body },
+                provide: cmd == "providecommand",
+                end,
+            })
+        }
+        "def" => {
+            let (name, next) = read_macro_name(chars, pos)?;
+            pos = next;
+            // Parameter text up to the body: `#1#2`.
+            let mut params = 0;
+            while pos < chars.len() && chars[pos] != '{' {
+                if chars[pos] == '#' {
+                    params += 1;
+                }
+                pos += 1;
+            }
+            let (body, end) = read_group(chars, pos)?;
+            Some(Definition {
+                name,
+                mac: Macro { params, default: None, body },
+                provide: false,
+                end,
+            })
+        }
+        "DeclareMathOperator" => {
+            let star = chars.get(skip_ws(chars, pos)) == Some(&'*');
+            pos = skip_star(chars, pos);
+            let (name, next) = read_macro_name(chars, pos)?;
+            let (text, end) = read_group(chars, next)?;
+            let body = format!("\\operatorname{}{{{}}}", if star { "*" } else { "" }, text);
+            Some(Definition {
+                name,
+                mac: Macro { params: 0, default: None, body },
+                provide: false,
+                end,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Replace `#1`..`#9` in `body` with `args`, and `##` with `#`.
+fn substitute(body: &str, args: &[String]) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut it = body.chars().peekable();
+    while let Some(c) = it.next() {
+        if c != '#' {
+            out.push(c);
+            continue;
+        }
+        match it.peek().copied() {
+            Some('#') => {
+                it.next();
+                out.push('#');
+            }
+            Some(d @ '1'..='9') => {
+                it.next();
+                let k = d as usize - '1' as usize;
+                out.push_str(args.get(k).map_or("", String::as_str));
+            }
+            _ => out.push('#'),
+        }
+    }
+    out
+}
+
+fn ends_with_control_word(s: &str) -> bool {
+    let word = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    word.ends_with('\\') && !word.ends_with("\\\\")
+}
+
+fn skip_ws(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+fn skip_star(chars: &[char], pos: usize) -> usize {
+    let pos = skip_ws(chars, pos);
+    if chars.get(pos) == Some(&'*') { pos + 1 } else { pos }
+}
+
+fn skip_comment(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && chars[pos] != '\n' {
+        pos += 1;
+    }
+    pos
+}
+
+/// Name of the control sequence whose letters start at `pos`: a run of
+/// letters, or a single other character.
+fn read_name(chars: &[char], pos: usize) -> (String, usize) {
+    let mut end = pos;
+    while end < chars.len() && chars[end].is_ascii_alphabetic() {
+        end += 1;
+    }
+    if end == pos && pos < chars.len() {
+        end += 1;
+    }
+    (chars[pos..end].iter().collect(), end)
+}
+
+/// The macro being defined: `\name` or `{\name}`.
+fn read_macro_name(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    let pos = skip_ws(chars, pos);
+    match chars.get(pos)? {
+        '\\' => Some(read_name(chars, pos + 1)),
+        '{' => {
+            let (inner, end) = read_group(chars, pos)?;
+            let inner = inner.trim();
+            let name = inner.strip_prefix('\\')?;
+            Some((name.to_string(), end))
+        }
+        _ => None,
+    }
+}
+
+/// Balanced `{...}` group at `pos` (after whitespace): its contents and the
+/// index past the closing brace.
+fn read_group(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    read_balanced(chars, skip_ws(chars, pos), '{', '}')
+}
+
+/// Optional `[...]` argument at `pos` (after whitespace), if there is one.
+fn read_optional(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    read_balanced(chars, skip_ws(chars, pos), '[', ']')
+}
+
+fn read_balanced(chars: &[char], pos: usize, open: char, close: char) -> Option<(String, usize)> {
+    if chars.get(pos) != Some(&open) {
+        return None;
+    }
+    let mut depth = 0usize;
+    // Brackets inside braces do not close an optional argument.
+    let mut braces = 0usize;
+    let mut i = pos;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                i += 2;
+                continue;
+            }
+            '{' if open != '{' => braces += 1,
+            '}' if open != '{' => braces = braces.saturating_sub(1),
+            c if c == open && braces == 0 => depth += 1,
+            c if c == close && braces == 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((chars[pos + 1..i].iter().collect(), i + 1));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// A mandatory argument: a braced group, a control sequence, or one character.
+fn read_argument(chars: &[char], pos: usize) -> (String, usize) {
+    let pos = skip_ws(chars, pos);
+    if let Some(group) = read_group(chars, pos) {
+        return group;
+    }
+    match chars.get(pos) {
+        Some('\\') => {
+            let (name, end) = read_name(chars, pos + 1);
+            (format!("\\{}", name), end)
+        }
+        Some(c) => (c.to_string(), pos + 1),
+        None => (String::new(), pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(table: &MacroTable, input: &str) -> String {
+        let (out, limit) = table.expand(input);
+        assert_eq!(limit, None);
+        out
+    }
+
+    #[test]
+    fn optional_argument_takes_its_default() {
+        let table = MacroTable::from_document(r"\newcommand{\f}[2][x]{#1+#2}");
+        assert_eq!(expand(&table, r"\f{y}"), "x+y");
+        assert_eq!(expand(&table, r"\f[z]{y}"), "z+y");
+    }
+
+    #[test]
+    fn providecommand_keeps_an_existing_definition() {
+        let table = MacroTable::from_document(r"\newcommand{\a}{1}\providecommand{\a}{2}\providecommand{\b}{3}");
+        assert_eq!(expand(&table, r"\a+\b"), "1+3");
+    }
+
+    #[test]
+    fn double_hash_becomes_a_single_hash() {
+        let table = MacroTable::from_document(r"\def\h{a##b}");
+        assert_eq!(expand(&table, r"\h"), "a#b");
+    }
+
+    #[test]
+    fn control_word_does_not_run_into_following_letters() {
+        let table = MacroTable::from_document(r"\newcommand{\op}[1]{\operatorname{#1}\alpha}");
+        assert_eq!(expand(&table, r"\op{x}y"), r"\operatorname{x}\alpha y");
+    }
+
+    #[test]
+    fn definitions_inside_a_snippet_apply_from_there_on() {
+        let table = MacroTable::new();
+        assert_eq!(expand(&table, r"\a \def\a{b}\a"), r"\a b");
+    }
+
+    #[test]
+    fn runaway_definitions_stop_at_the_limit() {
+        let table = MacroTable::from_document(r"\def\a{\a\a}\def\b{x\b}\def\c#1{\c{#1}}");
+        assert_eq!(table.expand(r"\a+1"), ("+1".to_string(), Some(ExpansionLimitExceeded)));
+        let (out, limit) = table.expand(r"\b");
+        assert_eq!((out.len(), limit), (MAX_DEPTH, Some(ExpansionLimitExceeded)));
+        assert_eq!(table.expand(r"\c{y}z").0, "z");
+    }
+
+    #[test]
+    fn layout_parameters_are_kept() {
+        let table = MacroTable::new();
+        let source = r"\renewcommand{\arraystretch}{1.5}x";
+        assert_eq!(expand(&table, source), source);
+    }
+}
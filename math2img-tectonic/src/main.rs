@@ -4,8 +4,12 @@ use std::process;
 use anyhow::Result;
 
 mod extract;
+mod macros;
+mod numbering;
 mod render;
 
+use macros::MacroTable;
+use numbering::Numbering;
 use render::Theme;
 
 struct Cli {
@@ -14,6 +18,7 @@ struct Cli {
     theme: Theme,
     font_size: f32,
     scale: f32,
+    numbering: bool,
 }
 
 fn parse_args() -> Cli {
@@ -23,6 +28,7 @@ fn parse_args() -> Cli {
     let mut theme = Theme::Dark;
     let mut font_size = 24.0f32;
     let mut scale = 3.0f32;
+    let mut numbering = false;
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -52,6 +58,9 @@ fn parse_args() -> Cli {
                     scale = val.parse().unwrap_or(3.0);
                 }
             }
+            "--numbering" => {
+                numbering = true;
+            }
             "-h" | "--help" => {
                 print_help();
                 process::exit(0);
@@ -81,7 +90,7 @@ fn parse_args() -> Cli {
         }
     };
 
-    Cli { input, output, theme, font_size, scale }
+    Cli { input, output, theme, font_size, scale, numbering }
 }
 
 fn print_help() {
@@ -97,6 +106,7 @@ OPTIONS:
     --theme <dark|light>     Color theme [default: dark]
     --font-size <N>          Font size in points [default: 24]
     --scale <N>              Render scale factor [default: 3.0]
+    --numbering              Keep equation numbers, counted across the document, and write labels.json
     -h, --help               Print help
     -V, --version            Print version"
     );
@@ -126,15 +136,32 @@ fn main() -> Result<()> {
 
     eprintln!("Found {} equation(s) in {:?}", equations.len(), cli.input);
 
+    let macros = MacroTable::from_document(&content);
+    if !macros.is_empty() {
+        eprintln!("Expanding {} user-defined macro(s)", macros.len());
+    }
+
     std::fs::create_dir_all(&cli.output)?;
 
+    let mut eq_numbers = Numbering::new();
     let mut success_count = 0;
     for (i, eq) in equations.iter().enumerate() {
         let output_path = cli.output.join(format!("equation_{:04}.png", i + 1));
 
+        let (source, limit) = macros.expand(&eq.content);
+        if let Some(limit) = limit {
+            eprintln!("  [WARN] equation {}: {}", i + 1, limit);
+        }
+        // LaTeX numbers the equation itself; it only needs the counter so far.
+        let counter = cli.numbering.then(|| {
+            let before = eq_numbers.counter();
+            eq_numbers.number(&source);
+            before
+        });
         match render::render_equation(
-            &eq.content,
+            &source,
             eq.is_display,
+            counter,
             &cli.theme,
             cli.font_size,
             cli.scale,
@@ -161,6 +188,11 @@ fn main() -> Result<()> {
         equations.len(),
         cli.output
     );
+    if cli.numbering {
+        let labels_path = cli.output.join("labels.json");
+        std::fs::write(&labels_path, eq_numbers.labels_json())?;
+        eprintln!("Wrote {} label(s) to {}", eq_numbers.labels().len(), labels_path.display());
+    }
     Ok(())
 }
 
@@ -0,0 +1,271 @@
+/// Equation numbering: a document-wide counter that follows LaTeX's rules
+/// for numbered environments, `\tag`, `\notag`/`\nonumber` and `\label`,
+/// together with the labels seen so far.
+///
+/// Works on the equation source rather than the parsed tree so that every
+/// backend numbers equations the same way.
+#[derive(Debug, Clone, Default)]
+pub struct Numbering {
+    counter: usize,
+    labels: Vec<(String, String)>,
+}
+
+impl Numbering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of automatically numbered equations (or rows) so far.
+    pub fn counter(&self) -> usize {
+        self.counter
+    }
+
+    /// Labels and the numbers they refer to, in document order.
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    /// Number one equation, advancing the counter. Returns the tag drawn
+    /// beside each row of a per-row environment (`align`, `gather`, ...), or
+    /// a single tag for anything else; `None` marks an unnumbered row.
+    pub fn number(&mut self, source: &str) -> Vec<Option<String>> {
+        let source = source.trim();
+        let env = environment(source);
+        let base = env.map(|e| e.trim_end_matches('*'));
+        let numbered = env.is_some_and(|e| !e.ends_with('*'))
+            && base.is_some_and(|b| PER_ROW.contains(&b) || PER_EQUATION.contains(&b));
+
+        let units = match base {
+            Some(b) if PER_ROW.contains(&b) => split_rows(source),
+            _ => vec![source],
+        };
+        units.into_iter().map(|unit| self.number_unit(unit, numbered)).collect()
+    }
+
+    fn number_unit(&mut self, unit: &str, numbered: bool) -> Option<String> {
+        let (number, tag) = if let Some((text, starred)) = find_tag(unit) {
+            let tag = if starred { text.clone() } else { format!("({})", text) };
+            (text, tag)
+        } else if !numbered || unit.contains("\\notag") || unit.contains("\\nonumber") {
+            return None;
+        } else {
+            self.counter += 1;
+            (self.counter.to_string(), format!("({})", self.counter))
+        };
+        for label in find_args(unit, "\\label") {
+            self.labels.push((label, number.clone()));
+        }
+        Some(tag)
+    }
+
+    /// The label map as a JSON object.
+    pub fn labels_json(&self) -> String {
+        let entries: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(label, number)| format!("  \"{}\": \"{}\"", json_escape(label), json_escape(number)))
+            .collect();
+        if entries.is_empty() {
+            "{}\n".to_string()
+        } else {
+            format!("{{\n{}\n}}\n", entries.join(",\n"))
+        }
+    }
+}
+
+/// Environments that get one number per row.
+const PER_ROW: &[&str] = &["align", "gather", "flalign", "alignat", "eqnarray"];
+/// Environments that get one number for the whole equation.
+const PER_EQUATION: &[&str] = &["equation", "multline"];
+
+/// Name of the environment an equation is wrapped in, if any.
+fn environment(source: &str) -> Option<&str> {
+    let rest = source.strip_prefix("\\begin{")?;
+    rest.find('}').map(|end| &rest[..end])
+}
+
+/// Rows of an alignment: the environment body split at `\\` outside braces
+/// and nested environments. A trailing empty row is dropped, as the parser does.
+fn split_rows(source: &str) -> Vec<&str> {
+    let body_start = body_start(source);
+    let body_end = source.rfind("\\end{").unwrap_or(source.len()).max(body_start);
+    let body = &source[body_start..body_end];
+
+    let bytes = body.as_bytes();
+    let mut rows = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b'\\' if body[i..].starts_with("\\begin{") => depth += 1,
+            b'\\' if body[i..].starts_with("\\end{") => depth = depth.saturating_sub(1),
+            b'\\' if depth == 0 && bytes.get(i + 1) == Some(&b'\\') => {
+                rows.push(&body[start..i]);
+                i += 2;
+                start = i;
+                continue;
+            }
+            b'\\' => i += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    rows.push(&body[start..]);
+    if rows.len() > 1 && rows.last().is_some_and(|r| r.trim().is_empty()) {
+        rows.pop();
+    }
+    rows
+}
+
+/// Where an environment's body starts: after `\begin{name}` and the
+/// arguments the environment itself takes, such as `alignat`'s column count.
+fn body_start(source: &str) -> usize {
+    let Some(env) = environment(source) else {
+        return 0;
+    };
+    let mut start = "\\begin{".len() + env.len() + 1;
+    let args = match env.trim_end_matches('*') {
+        "alignat" => 1,
+        _ => 0,
+    };
+    for _ in 0..args {
+        let rest = &source[start..];
+        let trimmed = rest.trim_start();
+        match braced(trimmed) {
+            Some(arg) => start += rest.len() - trimmed.len() + arg.len() + 2,
+            None => break,
+        }
+    }
+    start
+}
+
+/// Text of the first `\tag{..}` or `\tag*{..}`, and whether it was starred.
+fn find_tag(unit: &str) -> Option<(String, bool)> {
+    let mut from = 0;
+    while let Some(i) = unit[from..].find("\\tag") {
+        let after = &unit[from + i + 4..];
+        // Not `\tagfoo`.
+        if !after.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let starred = after.trim_start().starts_with('*');
+            let after = after.trim_start().trim_start_matches('*');
+            if let Some(text) = braced(after) {
+                return Some((text.trim().to_string(), starred));
+            }
+        }
+        from += i + 4;
+    }
+    None
+}
+
+/// Arguments of every occurrence of `cmd{..}` in `unit`.
+fn find_args(unit: &str, cmd: &str) -> Vec<String> {
+    unit.match_indices(cmd)
+        .filter_map(|(i, _)| braced(unit[i + cmd.len()..].trim_start()))
+        .map(|arg| arg.trim().to_string())
+        .collect()
+}
+
+/// Contents of the balanced `{..}` group at the start of `s`.
+fn braced(s: &str) -> Option<&str> {
+    if !s.starts_with('{') {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&s[1..i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[Option<&str>]) -> Vec<Option<String>> {
+        tags.iter().map(|t| t.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn numbered_environments_advance_the_counter() {
+        let mut n = Numbering::new();
+        assert_eq!(n.number(r"\begin{equation} x \end{equation}"), tags(&[Some("(1)")]));
+        assert_eq!(n.number("x + y"), tags(&[None]));
+        assert_eq!(n.number(r"\begin{equation*} x \end{equation*}"), tags(&[None]));
+        assert_eq!(n.number(r"\begin{multline} a \\ b \end{multline}"), tags(&[Some("(2)")]));
+        assert_eq!(n.counter(), 2);
+    }
+
+    #[test]
+    fn starred_alignments_number_no_rows() {
+        let mut n = Numbering::new();
+        assert_eq!(n.number(r"\begin{align*} a &= b \\ c &= d \end{align*}"), tags(&[None, None]));
+        assert_eq!(n.counter(), 0);
+    }
+
+    #[test]
+    fn notag_and_nonumber_skip_a_row() {
+        let mut n = Numbering::new();
+        let source = r"\begin{align} a \\ b \notag \\ c \nonumber \\ d \end{align}";
+        assert_eq!(n.number(source), tags(&[Some("(1)"), None, None, Some("(2)")]));
+    }
+
+    #[test]
+    fn tags_replace_the_number() {
+        let mut n = Numbering::new();
+        let source = r"\begin{gather} a \tag{A} \\ b \tag*{$\star$} \\ c \end{gather}";
+        assert_eq!(n.number(source), tags(&[Some("(A)"), Some(r"$\star$"), Some("(1)")]));
+        assert_eq!(n.number(r"x \tag{B}"), tags(&[Some("(B)")]));
+        assert_eq!(n.counter(), 1);
+    }
+
+    #[test]
+    fn labels_refer_to_their_own_row() {
+        let mut n = Numbering::new();
+        n.number(r"\begin{equation} x \label{first} \end{equation}");
+        n.number(r"\begin{align} a \\ b \label{second} \\ c \tag{C} \label{third} \end{align}");
+        let labels: Vec<(&str, &str)> = n.labels().iter().map(|(l, v)| (l.as_str(), v.as_str())).collect();
+        assert_eq!(labels, [("first", "1"), ("second", "3"), ("third", "C")]);
+        assert_eq!(n.labels_json(), "{\n  \"first\": \"1\",\n  \"second\": \"3\",\n  \"third\": \"C\"\n}\n");
+    }
+
+    #[test]
+    fn split_rows_skips_environment_arguments() {
+        let rows: Vec<&str> = split_rows(r"\begin{alignat}{2} a &= b \\ c &= d \end{alignat}").into_iter().map(str::trim).collect();
+        assert_eq!(rows, ["a &= b", "c &= d"]);
+        let rows: Vec<&str> = split_rows(r"\begin{alignat*} {2} {a} &= b \end{alignat*}").into_iter().map(str::trim).collect();
+        assert_eq!(rows, ["{a} &= b"]);
+    }
+
+    #[test]
+    fn split_rows_ignores_nested_breaks() {
+        let source = r"\begin{align} \begin{pmatrix} 1 \\ 2 \end{pmatrix} &= x \\ {a \\ b} \\ c \\ \end{align}";
+        let rows: Vec<&str> = split_rows(source).into_iter().map(str::trim).collect();
+        assert_eq!(rows, [r"\begin{pmatrix} 1 \\ 2 \end{pmatrix} &= x", r"{a \\ b}", "c"]);
+    }
+}
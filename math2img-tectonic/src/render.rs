@@ -32,8 +32,37 @@ impl Theme {
     }
 }
 
+/// Definitions for commands the native renderer accepts that none of the
+/// loaded packages provide. `\widecheck` is `\widehat` flipped upside down;
+/// the negated relations without a glyph of their own are built with `\not`.
+/// `\xlongequal` is extarrows' definition; the package itself would clash
+/// with the arrows mathtools already defines.
+const SHIMS: &str = r"\providecommand{\nequiv}{\not\equiv}
+\providecommand{\napprox}{\not\approx}
+\providecommand{\nsubset}{\not\subset}
+\providecommand{\nsupset}{\not\supset}
+\providecommand{\nni}{\not\ni}
+\providecommand{\notni}{\not\ni}
+\providecommand{\adots}{\iddots}
+\providecommand{\lparen}{(}
+\providecommand{\rparen}{)}
+\makeatletter
+\providecommand{\widecheck}[1]{{\mathpalette\m@thimg@widecheck{#1}}}
+\def\m@thimg@widecheck#1#2{%
+  \setbox\z@\hbox{\m@th$#1#2$}%
+  \setbox\tw@\hbox{\m@th$#1\widehat{\vrule\@width\z@\@height\ht\z@\vrule\@height\z@\@width\wd\z@}$}%
+  \dp\tw@-\ht\z@
+  \@tempdima\ht\z@ \advance\@tempdima2\ht\tw@ \divide\@tempdima\thr@@
+  \setbox\tw@\hbox{\raise\@tempdima\hbox{\scalebox{1}[-1]{\lower\@tempdima\box\tw@}}}%
+  {\ooalign{\box\tw@\cr\box\z@}}}
+\def\m@thimg@equalfill{\arrowfill@\Relbar\Relbar\Relbar}
+\providecommand{\xlongequal}[2][]{\ext@arrow 0055\m@thimg@equalfill{#1}{#2}}
+\makeatother";
+
 /// Wrap a LaTeX math snippet in a standalone document for rendering.
-fn wrap_equation(content: &str, is_display: bool, theme: &Theme, font_size: f32) -> String {
+/// `counter` is the equation counter so far when numbering is on; without it
+/// numbered environments are replaced by their starred variants.
+fn wrap_equation(content: &str, is_display: bool, counter: Option<usize>, theme: &Theme, font_size: f32) -> String {
     let size_cmd = match font_size as u32 {
         0..=9 => "\\scriptsize",
         10..=11 => "\\small",
@@ -45,14 +74,17 @@ fn wrap_equation(content: &str, is_display: bool, theme: &Theme, font_size: f32)
         _ => "\\Huge",
     };
 
-    // Strip equation numbering — replace numbered environments with starred variants
-    let content = content
-        .replace("\\begin{equation}", "\\begin{equation*}")
-        .replace("\\end{equation}", "\\end{equation*}")
-        .replace("\\begin{align}", "\\begin{align*}")
-        .replace("\\end{align}", "\\end{align*}")
-        .replace("\\begin{gather}", "\\begin{gather*}")
-        .replace("\\end{gather}", "\\end{gather*}");
+    let content = match counter {
+        Some(_) => content.to_string(),
+        // Strip equation numbering — replace numbered environments with starred variants
+        None => content
+            .replace("\\begin{equation}", "\\begin{equation*}")
+            .replace("\\end{equation}", "\\end{equation*}")
+            .replace("\\begin{align}", "\\begin{align*}")
+            .replace("\\end{align}", "\\end{align*}")
+            .replace("\\begin{gather}", "\\begin{gather*}")
+            .replace("\\end{gather}", "\\end{gather*}"),
+    };
 
     let math = if is_display {
         if content.contains("\\begin{") {
@@ -64,22 +96,34 @@ fn wrap_equation(content: &str, is_display: bool, theme: &Theme, font_size: f32)
         format!("${}$", content)
     };
 
+    // Continue the document's numbering from where it left off.
+    let counter_cmd = counter.map_or(String::new(), |n| format!("\\setcounter{{equation}}{{{}}}\n", n));
+
     format!(
         r#"\documentclass[border=12pt,varwidth=80cm]{{standalone}}
 \usepackage{{amsmath}}
+\usepackage{{mathtools}} % starred matrices, dcases and rcases, extensible arrows
 \usepackage{{amssymb}}
 \usepackage{{amsfonts}}
+\usepackage{{mathrsfs}}
 \usepackage{{xcolor}}
+\usepackage{{cancel}}
+\usepackage{{graphicx}}
+\usepackage{{stmaryrd}}
+\usepackage{{mathdots}}
+{shims}
 \pagecolor[rgb]{{{page_color}}}
 \color[rgb]{{{text_color}}}
 \pagestyle{{empty}}
 \begin{{document}}
 {size_cmd}
-{math}
+{counter_cmd}{math}
 \end{{document}}"#,
         page_color = theme.page_color(),
         text_color = theme.text_color(),
+        shims = SHIMS,
         size_cmd = size_cmd,
+        counter_cmd = counter_cmd,
         math = math,
     )
 }
@@ -88,12 +132,13 @@ fn wrap_equation(content: &str, is_display: bool, theme: &Theme, font_size: f32)
 pub fn render_equation(
     content: &str,
     is_display: bool,
+    counter: Option<usize>,
     theme: &Theme,
     font_size: f32,
     scale: f32,
     output: &Path,
 ) -> Result<()> {
-    let latex_src = wrap_equation(content, is_display, theme, font_size);
+    let latex_src = wrap_equation(content, is_display, counter, theme, font_size);
     let tmp = tempfile::tempdir()?;
     let tex_path = tmp.path().join("eq.tex");
     std::fs::write(&tex_path, &latex_src)?;
@@ -102,7 +147,7 @@ pub fn render_equation(
     let tec = Command::new("tectonic")
         .args(["-X", "compile", "--untrusted",
                "-o", &tmp.path().to_string_lossy(),
-               &tex_path.to_string_lossy().to_string()])
+               tex_path.to_string_lossy().as_ref()])
         .output()
         .context("Failed to run tectonic. Is it installed?")?;
 
@@ -123,8 +168,8 @@ pub fn render_equation(
     // Try pdftoppm first (poppler — most reliable)
     let r = Command::new("pdftoppm")
         .args(["-png", "-r", &dpi.to_string(), "-singlefile",
-               &pdf_path.to_string_lossy().to_string(),
-               &png_stem.to_string_lossy().to_string()])
+               pdf_path.to_string_lossy().as_ref(),
+               png_stem.to_string_lossy().as_ref()])
         .output();
 
     let png_path = tmp.path().join("eq.png");
@@ -134,17 +179,17 @@ pub fn render_equation(
             // Fallback: sips (macOS)
             let r2 = Command::new("sips")
                 .args(["-s", "format", "png",
-                       &pdf_path.to_string_lossy().to_string(),
-                       "--out", &png_path.to_string_lossy().to_string()])
+                       pdf_path.to_string_lossy().as_ref(),
+                       "--out", png_path.to_string_lossy().as_ref()])
                 .output();
             match r2 {
                 Ok(o2) if o2.status.success() && png_path.exists() => true,
                 _ => {
                     // Fallback: mutool
                     let r3 = Command::new("mutool")
-                        .args(["draw", "-o", &png_path.to_string_lossy().to_string(),
+                        .args(["draw", "-o", png_path.to_string_lossy().as_ref(),
                                "-r", &dpi.to_string(),
-                               &pdf_path.to_string_lossy().to_string()])
+                               pdf_path.to_string_lossy().as_ref()])
                         .output();
                     matches!(r3, Ok(o3) if o3.status.success() && png_path.exists())
                 }
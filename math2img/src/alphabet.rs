@@ -0,0 +1,177 @@
+/// Math alphabets: maps plain letters and digits into the Unicode Mathematical
+/// Alphanumeric Symbols block (U+1D400–U+1D7FF), which STIX Two Math covers.
+use crate::parser::MathVariant;
+
+/// First code points of the capital and small Latin letters of an alphabet.
+fn latin_start(variant: MathVariant) -> Option<(u32, u32)> {
+    match variant {
+        MathVariant::Normal => None,
+        MathVariant::Bold => Some((0x1D400, 0x1D41A)),
+        MathVariant::Italic => Some((0x1D434, 0x1D44E)),
+        MathVariant::BoldItalic => Some((0x1D468, 0x1D482)),
+        MathVariant::Script => Some((0x1D49C, 0x1D4B6)),
+        MathVariant::BoldScript => Some((0x1D4D0, 0x1D4EA)),
+        MathVariant::Fraktur => Some((0x1D504, 0x1D51E)),
+        MathVariant::DoubleStruck => Some((0x1D538, 0x1D552)),
+        MathVariant::BoldFraktur => Some((0x1D56C, 0x1D586)),
+        MathVariant::SansSerif => Some((0x1D5A0, 0x1D5BA)),
+        MathVariant::SansSerifBold => Some((0x1D5D4, 0x1D5EE)),
+        MathVariant::SansSerifItalic => Some((0x1D608, 0x1D622)),
+        MathVariant::Monospace => Some((0x1D670, 0x1D68A)),
+    }
+}
+
+/// First code point of the digits of an alphabet; the rest fall back to bold or upright.
+fn digit_start(variant: MathVariant) -> Option<u32> {
+    match variant {
+        MathVariant::Bold | MathVariant::BoldItalic => Some(0x1D7CE),
+        MathVariant::DoubleStruck => Some(0x1D7D8),
+        MathVariant::SansSerif | MathVariant::SansSerifItalic => Some(0x1D7E2),
+        MathVariant::SansSerifBold => Some(0x1D7EC),
+        MathVariant::Monospace => Some(0x1D7F6),
+        _ => None,
+    }
+}
+
+/// First code points of the capital and small Greek letters of an alphabet.
+fn greek_start(variant: MathVariant) -> Option<(u32, u32)> {
+    match variant {
+        MathVariant::Bold => Some((0x1D6A8, 0x1D6C2)),
+        MathVariant::Italic => Some((0x1D6E2, 0x1D6FC)),
+        MathVariant::BoldItalic => Some((0x1D71C, 0x1D736)),
+        MathVariant::SansSerifBold => Some((0x1D756, 0x1D770)),
+        _ => None,
+    }
+}
+
+/// Letters that were encoded in Letterlike Symbols before the math block,
+/// leaving holes in it.
+fn letterlike(variant: MathVariant, ch: char) -> Option<char> {
+    let mapped = match (variant, ch) {
+        (MathVariant::Italic, 'h') => '\u{210E}',
+        (MathVariant::Script, 'B') => '\u{212C}',
+        (MathVariant::Script, 'E') => '\u{2130}',
+        (MathVariant::Script, 'F') => '\u{2131}',
+        (MathVariant::Script, 'H') => '\u{210B}',
+        (MathVariant::Script, 'I') => '\u{2110}',
+        (MathVariant::Script, 'L') => '\u{2112}',
+        (MathVariant::Script, 'M') => '\u{2133}',
+        (MathVariant::Script, 'R') => '\u{211B}',
+        (MathVariant::Script, 'e') => '\u{212F}',
+        (MathVariant::Script, 'g') => '\u{210A}',
+        (MathVariant::Script, 'o') => '\u{2134}',
+        (MathVariant::Fraktur, 'C') => '\u{212D}',
+        (MathVariant::Fraktur, 'H') => '\u{210C}',
+        (MathVariant::Fraktur, 'I') => '\u{2111}',
+        (MathVariant::Fraktur, 'R') => '\u{211C}',
+        (MathVariant::Fraktur, 'Z') => '\u{2128}',
+        (MathVariant::DoubleStruck, 'C') => '\u{2102}',
+        (MathVariant::DoubleStruck, 'H') => '\u{210D}',
+        (MathVariant::DoubleStruck, 'N') => '\u{2115}',
+        (MathVariant::DoubleStruck, 'P') => '\u{2119}',
+        (MathVariant::DoubleStruck, 'Q') => '\u{211A}',
+        (MathVariant::DoubleStruck, 'R') => '\u{211D}',
+        (MathVariant::DoubleStruck, 'Z') => '\u{2124}',
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+/// The character `ch` takes in `variant`, or `ch` itself when the alphabet has
+/// no such letter (punctuation, operators, Greek in script style, ...).
+pub(crate) fn map_char(variant: MathVariant, ch: char) -> char {
+    if let Some(mapped) = letterlike(variant, ch) {
+        return mapped;
+    }
+    let offset = |start: u32, first: char| char::from_u32(start + (ch as u32 - first as u32)).unwrap_or(ch);
+    match ch {
+        'A'..='Z' => latin_start(variant).map_or(ch, |(upper, _)| offset(upper, 'A')),
+        'a'..='z' => latin_start(variant).map_or(ch, |(_, lower)| offset(lower, 'a')),
+        '0'..='9' => digit_start(variant).map_or(ch, |start| offset(start, '0')),
+        // U+03A2 is unassigned; the math block puts ϴ in that slot.
+        '\u{0391}'..='\u{03A9}' if ch != '\u{03A2}' => {
+            greek_start(variant).map_or(ch, |(upper, _)| offset(upper, '\u{0391}'))
+        }
+        '\u{03B1}'..='\u{03C9}' => greek_start(variant).map_or(ch, |(_, lower)| offset(lower, '\u{03B1}')),
+        _ => ch,
+    }
+}
+
+/// The bold counterpart of `variant`, for alphabets nested inside `\mathbf`
+/// or `\boldsymbol`.
+pub(crate) fn embolden(variant: MathVariant) -> MathVariant {
+    match variant {
+        MathVariant::Normal => MathVariant::Bold,
+        MathVariant::Italic => MathVariant::BoldItalic,
+        MathVariant::Script => MathVariant::BoldScript,
+        MathVariant::Fraktur => MathVariant::BoldFraktur,
+        MathVariant::SansSerif => MathVariant::SansSerifBold,
+        other => other,
+    }
+}
+
+/// Alphabet a symbol gets outside any alphabet command, following TeX: Latin
+/// letters and lowercase Greek are italic, digits and capital Greek upright.
+pub(crate) fn default_variant(ch: char) -> Option<MathVariant> {
+    match ch {
+        'A'..='Z' | 'a'..='z' | '\u{03B1}'..='\u{03C9}' => Some(MathVariant::Italic),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters_and_digits_map_into_the_math_block() {
+        assert_eq!(map_char(MathVariant::Bold, 'A'), '\u{1D400}');
+        assert_eq!(map_char(MathVariant::Bold, 'z'), '\u{1D433}');
+        assert_eq!(map_char(MathVariant::DoubleStruck, '0'), '\u{1D7D8}');
+        assert_eq!(map_char(MathVariant::Monospace, '9'), '\u{1D7FF}');
+        assert_eq!(map_char(MathVariant::Normal, 'x'), 'x');
+    }
+
+    #[test]
+    fn letterlike_holes_use_the_older_code_points() {
+        assert_eq!(map_char(MathVariant::Italic, 'h'), '\u{210E}');
+        assert_eq!(map_char(MathVariant::DoubleStruck, 'R'), '\u{211D}');
+        assert_eq!(map_char(MathVariant::Fraktur, 'C'), '\u{212D}');
+        assert_eq!(map_char(MathVariant::Script, 'e'), '\u{212F}');
+    }
+
+    #[test]
+    fn greek_maps_only_where_the_alphabet_has_it() {
+        assert_eq!(map_char(MathVariant::Bold, '\u{03B1}'), '\u{1D6C2}');
+        assert_eq!(map_char(MathVariant::Italic, '\u{0391}'), '\u{1D6E2}');
+        assert_eq!(map_char(MathVariant::Script, '\u{03B1}'), '\u{03B1}');
+        // U+03A2 is unassigned and stays as it is.
+        assert_eq!(map_char(MathVariant::Bold, '\u{03A2}'), '\u{03A2}');
+    }
+
+    #[test]
+    fn punctuation_is_unchanged() {
+        assert_eq!(map_char(MathVariant::Bold, '+'), '+');
+        assert_eq!(map_char(MathVariant::DoubleStruck, '('), '(');
+    }
+
+    #[test]
+    fn lowercase_double_struck_has_no_holes() {
+        assert_eq!(map_char(MathVariant::DoubleStruck, 'x'), '\u{1D569}');
+    }
+
+    #[test]
+    fn bold_alphabets() {
+        assert_eq!(embolden(MathVariant::Italic), MathVariant::BoldItalic);
+        assert_eq!(embolden(MathVariant::Fraktur), MathVariant::BoldFraktur);
+        assert_eq!(embolden(MathVariant::Monospace), MathVariant::Monospace);
+    }
+
+    #[test]
+    fn letters_default_to_italic() {
+        assert_eq!(default_variant('x'), Some(MathVariant::Italic));
+        assert_eq!(default_variant('\u{03B1}'), Some(MathVariant::Italic));
+        assert_eq!(default_variant('\u{0393}'), None);
+        assert_eq!(default_variant('1'), None);
+    }
+}
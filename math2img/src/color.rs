@@ -0,0 +1,126 @@
+/// Color expressions as xcolor reads them: the base set of named colors, the
+/// `rgb`, `RGB`, `HTML`, `gray` and `cmyk` models, and `!` mixing.
+use crate::parser::Color;
+
+/// xcolor's base colors (those available without `dvipsnames` and friends).
+fn named(name: &str) -> Option<[f32; 3]> {
+    let rgb = match name {
+        "red" => [1.0, 0.0, 0.0],
+        "green" => [0.0, 1.0, 0.0],
+        "blue" => [0.0, 0.0, 1.0],
+        "cyan" => [0.0, 1.0, 1.0],
+        "magenta" => [1.0, 0.0, 1.0],
+        "yellow" => [1.0, 1.0, 0.0],
+        "black" => [0.0, 0.0, 0.0],
+        "white" => [1.0, 1.0, 1.0],
+        "gray" => [0.5, 0.5, 0.5],
+        "darkgray" => [0.25, 0.25, 0.25],
+        "lightgray" => [0.75, 0.75, 0.75],
+        "brown" => [0.75, 0.5, 0.25],
+        "lime" => [0.75, 1.0, 0.0],
+        "olive" => [0.5, 0.5, 0.0],
+        "orange" => [1.0, 0.5, 0.0],
+        "pink" => [1.0, 0.75, 0.75],
+        "purple" => [0.75, 0.0, 0.25],
+        "teal" => [0.0, 0.5, 0.5],
+        "violet" => [0.5, 0.0, 0.5],
+        _ => return None,
+    };
+    Some(rgb)
+}
+
+/// Read `spec` in the color model `model` (`\color[model]{spec}`), or as a
+/// color expression when there is no model: a name, optionally mixed with
+/// others (`red!30!blue` is 30% red and 70% blue, `red!50` half red and half
+/// white) and complemented by a leading `-`.
+pub(crate) fn parse_color(model: Option<&str>, spec: &str) -> Option<Color> {
+    let spec = spec.trim();
+    let rgb = match model.map(str::trim) {
+        None | Some("named") => expression(spec)?,
+        Some(model) => with_model(model, spec)?,
+    };
+    Some(Color(rgb.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)))
+}
+
+fn with_model(model: &str, spec: &str) -> Option<[f32; 3]> {
+    let values: Vec<f32> = if model == "HTML" {
+        if spec.len() != 6 || !spec.is_ascii() {
+            return None;
+        }
+        (0..3)
+            .map(|i| u8::from_str_radix(&spec[2 * i..2 * i + 2], 16).ok().map(|v| v as f32 / 255.0))
+            .collect::<Option<_>>()?
+    } else {
+        spec.split([',', ' ']).filter(|s| !s.is_empty()).map(|s| s.trim().parse().ok()).collect::<Option<_>>()?
+    };
+    match (model, values.as_slice()) {
+        ("HTML" | "rgb", &[r, g, b]) => Some([r, g, b]),
+        ("RGB", &[r, g, b]) => Some([r / 255.0, g / 255.0, b / 255.0]),
+        ("gray", &[v]) => Some([v, v, v]),
+        ("cmy", &[c, m, y]) => Some([1.0 - c, 1.0 - m, 1.0 - y]),
+        ("cmyk", &[c, m, y, k]) => Some([1.0 - (c + k).min(1.0), 1.0 - (m + k).min(1.0), 1.0 - (y + k).min(1.0)]),
+        _ => None,
+    }
+}
+
+fn expression(spec: &str) -> Option<[f32; 3]> {
+    let (complement, spec) = match spec.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, spec),
+    };
+    let mut parts = spec.split('!').map(str::trim);
+    let mut rgb = named(parts.next()?)?;
+    while let Some(percent) = parts.next() {
+        let t = percent.parse::<f32>().ok()?.clamp(0.0, 100.0) / 100.0;
+        let other = match parts.next() {
+            Some(name) => named(name)?,
+            None => [1.0; 3],
+        };
+        rgb = [0, 1, 2].map(|i| t * rgb[i] + (1.0 - t) * other[i]);
+    }
+    Some(if complement { rgb.map(|v| 1.0 - v) } else { rgb })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(model: Option<&str>, spec: &str) -> Option<[u8; 3]> {
+        parse_color(model, spec).map(|c| c.0)
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(rgb(None, "red"), Some([255, 0, 0]));
+        assert_eq!(rgb(None, " teal "), Some([0, 128, 128]));
+        assert_eq!(rgb(Some("named"), "black"), Some([0, 0, 0]));
+        assert_eq!(rgb(None, "chartreuse"), None);
+    }
+
+    #[test]
+    fn color_models() {
+        assert_eq!(rgb(Some("HTML"), "1E90FF"), Some([30, 144, 255]));
+        assert_eq!(rgb(Some("rgb"), "1, 0.5, 0"), Some([255, 128, 0]));
+        assert_eq!(rgb(Some("RGB"), "255,128,0"), Some([255, 128, 0]));
+        assert_eq!(rgb(Some("gray"), "0.5"), Some([128, 128, 128]));
+        assert_eq!(rgb(Some("cmyk"), "0,1,1,0"), Some([255, 0, 0]));
+        assert_eq!(rgb(Some("HTML"), "XYZ"), None);
+        assert_eq!(rgb(Some("rgb"), "1,0"), None);
+        assert_eq!(rgb(Some("hsb"), "0,1,1"), None);
+    }
+
+    #[test]
+    fn mixing() {
+        assert_eq!(rgb(None, "red!50"), Some([255, 128, 128]));
+        assert_eq!(rgb(None, "red!30!blue"), Some([77, 0, 179]));
+        assert_eq!(rgb(None, "red!50!blue!50"), Some([191, 128, 191]));
+        assert_eq!(rgb(None, "red!150"), Some([255, 0, 0]));
+        assert_eq!(rgb(None, "red!x"), None);
+    }
+
+    #[test]
+    fn complement() {
+        assert_eq!(rgb(None, "-red"), Some([0, 255, 255]));
+        assert_eq!(rgb(None, "-red!50"), Some([0, 128, 128]));
+    }
+}
@@ -1,5 +1,6 @@
 use regex_lite::Regex;
 
+/// A math equation found in a source document.
 #[derive(Debug, Clone)]
 pub struct Equation {
     pub content: String,
@@ -25,16 +26,16 @@ pub fn extract_from_latex(content: &str) -> Vec<Equation> {
     for cap in env_re.captures_iter(content) {
         let m = cap.get(0).unwrap();
         // Verify opening and closing env names match
-        if cap.get(1).map(|m| m.as_str()) == cap.get(3).map(|m| m.as_str()) {
-            if !overlaps(&used, m.start(), m.end()) {
-                used.push((m.start(), m.end()));
-                equations.push(Equation {
-                    content: m.as_str().to_string(),
-                    is_display: true,
-                    start: m.start(),
-                    end: m.end(),
-                });
-            }
+        if cap.get(1).map(|m| m.as_str()) == cap.get(3).map(|m| m.as_str())
+            && !overlaps(&used, m.start(), m.end())
+        {
+            used.push((m.start(), m.end()));
+            equations.push(Equation {
+                content: m.as_str().to_string(),
+                is_display: true,
+                start: m.start(),
+                end: m.end(),
+            });
         }
     }
 
@@ -0,0 +1,37 @@
+//! Extract math equations from LaTeX/Markdown and render them as PNG or SVG images.
+//!
+//! The pieces used by the `math2img` CLI are exposed so the renderer can be
+//! embedded directly:
+//!
+//! ```no_run
+//! let png = math2img::render_to_png_bytes(r"\frac{a}{b}", &math2img::Options::default())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod alphabet;
+mod color;
+pub mod extract;
+pub mod macros;
+mod mathfont;
+pub mod numbering;
+pub mod parser;
+pub mod render;
+mod svg;
+
+pub use extract::Equation;
+pub use macros::MacroTable;
+pub use numbering::Numbering;
+pub use parser::{parse, MathNode, ParseError};
+pub use render::{Options, OutputFormat, Renderer, Theme};
+
+/// Parse a LaTeX math snippet and render it to PNG-encoded bytes.
+pub fn render_to_png_bytes(latex: &str, options: &Options) -> anyhow::Result<Vec<u8>> {
+    let ast = parser::parse(latex);
+    Renderer::new().render_png(&ast, options)
+}
+
+/// Parse a LaTeX math snippet and render it to a standalone SVG document.
+pub fn render_to_svg_string(latex: &str, options: &Options) -> anyhow::Result<String> {
+    let ast = parser::parse(latex);
+    Renderer::new().render_svg(&ast, options)
+}
@@ -3,11 +3,7 @@ use std::process;
 
 use anyhow::Result;
 
-mod extract;
-mod parser;
-mod render;
-
-use render::{Renderer, Theme};
+use math2img::{extract, parser, MacroTable, MathNode, Numbering, Options, OutputFormat, Renderer, Theme};
 
 struct Cli {
     input: PathBuf,
@@ -15,6 +11,9 @@ struct Cli {
     theme: Theme,
     font_size: f32,
     scale: f32,
+    format: OutputFormat,
+    strict: bool,
+    numbering: bool,
 }
 
 fn parse_args() -> Cli {
@@ -24,6 +23,9 @@ fn parse_args() -> Cli {
     let mut theme = Theme::Dark;
     let mut font_size = 24.0f32;
     let mut scale = 3.0f32;
+    let mut format = OutputFormat::Png;
+    let mut strict = false;
+    let mut numbering = false;
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -53,6 +55,25 @@ fn parse_args() -> Cli {
                     scale = val.parse().unwrap_or(3.0);
                 }
             }
+            "--format" => {
+                if let Some(val) = args.next() {
+                    format = match val.as_str() {
+                        "png" => OutputFormat::Png,
+                        "svg" => OutputFormat::Svg,
+                        other => {
+                            eprintln!("Unknown format: {} (expected png|svg)", other);
+                            print_help();
+                            process::exit(1);
+                        }
+                    };
+                }
+            }
+            "--strict" => {
+                strict = true;
+            }
+            "--numbering" => {
+                numbering = true;
+            }
             "-h" | "--help" => {
                 print_help();
                 process::exit(0);
@@ -83,22 +104,25 @@ fn parse_args() -> Cli {
         }
     };
 
-    Cli { input, output, theme, font_size, scale }
+    Cli { input, output, theme, font_size, scale, format, strict, numbering }
 }
 
 fn print_help() {
     eprintln!(
-        "math2img - Extract math equations from LaTeX/Markdown and render as PNG
+        "math2img - Extract math equations from LaTeX/Markdown and render as PNG or SVG
 
 USAGE:
     math2img -i <INPUT> [OPTIONS]
 
 OPTIONS:
     -i, --input <FILE>       Input file (LaTeX/TeX or Markdown)
-    -o, --output <DIR>       Output directory for PNG images [default: .]
+    -o, --output <DIR>       Output directory for images [default: .]
     --theme <dark|light>     Color theme [default: dark]
     --font-size <N>          Font size in points [default: 24]
     --scale <N>              Render scale factor [default: 3.0]
+    --format <png|svg>       Output image format [default: png]
+    --strict                 Skip equations that fail to parse instead of rendering them with warnings
+    --numbering              Number equations like LaTeX (\\tag, \\notag, \\label) and write labels.json
     -h, --help               Print help
     -V, --version            Print version"
     );
@@ -128,17 +152,76 @@ fn main() -> Result<()> {
 
     eprintln!("Found {} equation(s) in {:?}", equations.len(), cli.input);
 
+    let macros = MacroTable::from_document(&content);
+    if !macros.is_empty() {
+        eprintln!("Expanding {} user-defined macro(s)", macros.len());
+    }
+
     std::fs::create_dir_all(&cli.output)?;
 
     let renderer = Renderer::new();
+    let mut eq_numbers = Numbering::new();
 
     let mut success_count = 0;
+    let mut parse_failures = 0;
     for (i, eq) in equations.iter().enumerate() {
-        let output_path = cli.output.join(format!("equation_{:04}.png", i + 1));
+        let output_path = cli
+            .output
+            .join(format!("equation_{:04}.{}", i + 1, cli.format.extension()));
 
-        let ast = parser::parse(&eq.content);
+        let (source, limit) = macros.expand(&eq.content);
+        let (mut ast, errors) = parser::parse_with_warnings(&source);
+        if cli.numbering {
+            let tags = eq_numbers.number(&source);
+            let rows = match &ast {
+                MathNode::Aligned { kind, rows } if *kind != parser::AlignKind::Multline => rows.len(),
+                _ => 1,
+            };
+            if tags.len() > 1 && tags.len() != rows {
+                eprintln!(
+                    "  [WARN] equation {}: numbered {} row(s) but found {}; tags may not line up with their rows",
+                    i + 1,
+                    tags.len(),
+                    rows
+                );
+            }
+            if tags.iter().any(Option::is_some) {
+                let tags = tags.iter().map(|t| t.as_deref().map(parser::parse_tag)).collect();
+                ast = MathNode::Tagged { content: Box::new(ast), tags };
+            }
+        }
+        let severity = if cli.strict { "ERROR" } else { "WARN" };
+        if let Some(limit) = limit {
+            eprintln!("  [{}] equation {}: {}", severity, i + 1, limit);
+        }
+        for err in &errors {
+            let (line, col) = line_col(&content, source_offset(&content, eq, &source, err.span.start));
+            eprintln!(
+                "  [{}] equation {} at line {}, column {}: {}",
+                severity,
+                i + 1,
+                line,
+                col,
+                err
+            );
+        }
+        if cli.strict && (limit.is_some() || !errors.is_empty()) {
+            parse_failures += 1;
+            continue;
+        }
+
+        let options = Options {
+            theme: cli.theme.clone(),
+            font_size: cli.font_size,
+            scale: cli.scale,
+            display: eq.is_display,
+        };
+        let rendered = match cli.format {
+            OutputFormat::Png => renderer.render_png(&ast, &options),
+            OutputFormat::Svg => renderer.render_svg(&ast, &options).map(String::into_bytes),
+        };
 
-        match renderer.render_equation(&ast, &cli.theme, cli.font_size, cli.scale, &output_path) {
+        match rendered.and_then(|bytes| Ok(std::fs::write(&output_path, bytes)?)) {
             Ok(()) => {
                 eprintln!(
                     "  [{}] {} -> {}",
@@ -160,9 +243,38 @@ fn main() -> Result<()> {
         equations.len(),
         cli.output
     );
+    if cli.numbering {
+        let labels_path = cli.output.join("labels.json");
+        std::fs::write(&labels_path, eq_numbers.labels_json())?;
+        eprintln!("Wrote {} label(s) to {}", eq_numbers.labels().len(), labels_path.display());
+    }
+    if parse_failures > 0 {
+        eprintln!("{} equation(s) failed to parse", parse_failures);
+        process::exit(1);
+    }
     Ok(())
 }
 
+/// Byte offset in the document of `offset` within an equation's source. When
+/// macro expansion rewrote the equation, offsets no longer line up with the
+/// document and the start of the equation is used instead.
+fn source_offset(doc: &str, eq: &extract::Equation, source: &str, offset: usize) -> usize {
+    if source != eq.content {
+        return eq.start;
+    }
+    doc[eq.start..eq.end]
+        .find(eq.content.as_str())
+        .map_or(eq.start, |i| eq.start + i + offset)
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+fn line_col(doc: &str, offset: usize) -> (usize, usize) {
+    let before = &doc[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     let s = s.replace('\n', " ");
     if s.len() <= max_len {
@@ -171,3 +283,26 @@ fn truncate(s: &str, max_len: usize) -> String {
         format!("{}...", &s[..max_len])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_counts_characters_from_one() {
+        let doc = "first\nsé $x}$\n";
+        assert_eq!(line_col(doc, 0), (1, 1));
+        assert_eq!(line_col(doc, doc.find('}').unwrap()), (2, 6));
+    }
+
+    #[test]
+    fn source_offset_maps_into_the_document() {
+        let doc = "text\n$$a}b$$\n";
+        let eq = &extract::extract_from_latex(doc)[0];
+        let offset = source_offset(doc, eq, &eq.content, 1);
+        assert_eq!(&doc[offset..offset + 1], "}");
+        assert_eq!(line_col(doc, offset), (2, 4));
+        // Expanded macros no longer line up, so the equation start is used.
+        assert_eq!(source_offset(doc, eq, "a}b + c", 1), eq.start);
+    }
+}
@@ -0,0 +1,310 @@
+/// The embedded math font: glyph outlines through ab_glyph, layout metrics from
+/// the OpenType MATH table.
+///
+/// Every length returned here is a fraction of the font size, so callers
+/// multiply by the pixel size they are laying out at.
+use ab_glyph::{Font, FontRef, GlyphId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+macro_rules! math_constants {
+    ($($name:ident),* $(,)?) => {
+        /// MathConstants table values, in units of the font size.
+        ///
+        /// The whole table is read up front; not every constant drives layout.
+        #[allow(dead_code)]
+        #[derive(Debug, Clone)]
+        pub(crate) struct MathConstants {
+            $(pub $name: f32,)*
+            pub script_percent_scale_down: f32,
+            pub script_script_percent_scale_down: f32,
+            pub delimited_sub_formula_min_height: f32,
+            pub display_operator_min_height: f32,
+            pub radical_degree_bottom_raise_percent: f32,
+        }
+
+        impl MathConstants {
+            fn read(c: &ttf_parser::math::Constants, unit: f32) -> Self {
+                MathConstants {
+                    $($name: c.$name().value as f32 * unit,)*
+                    script_percent_scale_down: c.script_percent_scale_down() as f32 / 100.0,
+                    script_script_percent_scale_down: c.script_script_percent_scale_down() as f32 / 100.0,
+                    delimited_sub_formula_min_height: c.delimited_sub_formula_min_height() as f32 * unit,
+                    display_operator_min_height: c.display_operator_min_height() as f32 * unit,
+                    radical_degree_bottom_raise_percent: c.radical_degree_bottom_raise_percent() as f32 / 100.0,
+                }
+            }
+        }
+    };
+}
+
+math_constants! {
+    math_leading,
+    axis_height,
+    accent_base_height,
+    flattened_accent_base_height,
+    subscript_shift_down,
+    subscript_top_max,
+    subscript_baseline_drop_min,
+    superscript_shift_up,
+    superscript_shift_up_cramped,
+    superscript_bottom_min,
+    superscript_baseline_drop_max,
+    sub_superscript_gap_min,
+    superscript_bottom_max_with_subscript,
+    space_after_script,
+    upper_limit_gap_min,
+    upper_limit_baseline_rise_min,
+    lower_limit_gap_min,
+    lower_limit_baseline_drop_min,
+    stack_top_shift_up,
+    stack_top_display_style_shift_up,
+    stack_bottom_shift_down,
+    stack_bottom_display_style_shift_down,
+    stack_gap_min,
+    stack_display_style_gap_min,
+    stretch_stack_top_shift_up,
+    stretch_stack_bottom_shift_down,
+    stretch_stack_gap_above_min,
+    stretch_stack_gap_below_min,
+    fraction_numerator_shift_up,
+    fraction_numerator_display_style_shift_up,
+    fraction_denominator_shift_down,
+    fraction_denominator_display_style_shift_down,
+    fraction_numerator_gap_min,
+    fraction_num_display_style_gap_min,
+    fraction_rule_thickness,
+    fraction_denominator_gap_min,
+    fraction_denom_display_style_gap_min,
+    skewed_fraction_horizontal_gap,
+    skewed_fraction_vertical_gap,
+    overbar_vertical_gap,
+    overbar_rule_thickness,
+    overbar_extra_ascender,
+    underbar_vertical_gap,
+    underbar_rule_thickness,
+    underbar_extra_descender,
+    radical_vertical_gap,
+    radical_display_style_vertical_gap,
+    radical_rule_thickness,
+    radical_extra_ascender,
+    radical_kern_before_degree,
+    radical_kern_after_degree,
+}
+
+/// Ink extents of a glyph (y grows upwards, as in the font).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InkBox {
+    pub x_min: f32,
+    pub x_max: f32,
+    pub ascent: f32,
+    pub descent: f32,
+}
+
+/// One glyph of an extensible assembly, `offset` from the start of the
+/// assembly along the stretch direction (bottom-up or left-to-right).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PlacedPart {
+    pub glyph: GlyphId,
+    pub offset: f32,
+}
+
+/// A glyph grown to a requested size: a larger pre-drawn variant, or an
+/// assembly of parts when no variant is big enough.
+#[derive(Debug, Clone)]
+pub(crate) enum Stretched {
+    Variant(GlyphId),
+    Assembly { parts: Vec<PlacedPart>, length: f32 },
+}
+
+pub(crate) struct MathFont<'a> {
+    pub font: FontRef<'a>,
+    face: ttf_parser::Face<'a>,
+    math: ttf_parser::math::Table<'a>,
+    pub constants: MathConstants,
+    /// Font units → fraction of the font size.
+    unit: f32,
+    ink: RefCell<HashMap<GlyphId, Option<InkBox>>>,
+}
+
+impl<'a> MathFont<'a> {
+    pub fn new(data: &'a [u8]) -> anyhow::Result<Self> {
+        let font = FontRef::try_from_slice(data)
+            .map_err(|e| anyhow::anyhow!("Font load error: {}", e))?;
+        let face = ttf_parser::Face::parse(data, 0)
+            .map_err(|e| anyhow::anyhow!("Font load error: {}", e))?;
+        let math = face
+            .tables()
+            .math
+            .ok_or_else(|| anyhow::anyhow!("Font has no OpenType MATH table"))?;
+        let unit = 1.0 / font.height_unscaled();
+        let constants = math
+            .constants
+            .as_ref()
+            .map(|c| MathConstants::read(c, unit))
+            .ok_or_else(|| anyhow::anyhow!("Font has no MathConstants table"))?;
+        Ok(MathFont {
+            font,
+            face,
+            math,
+            constants,
+            unit,
+            ink: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn glyph(&self, ch: char) -> GlyphId {
+        self.font.glyph_id(ch)
+    }
+
+    pub fn advance(&self, gid: GlyphId) -> f32 {
+        self.font.h_advance_unscaled(gid) * self.unit
+    }
+
+    /// Ink bounding box, or `None` for blank glyphs such as the space.
+    pub fn ink(&self, gid: GlyphId) -> Option<InkBox> {
+        *self.ink.borrow_mut().entry(gid).or_insert_with(|| {
+            let r = self.face.glyph_bounding_box(ttf_parser::GlyphId(gid.0))?;
+            Some(InkBox {
+                x_min: r.x_min as f32 * self.unit,
+                x_max: r.x_max as f32 * self.unit,
+                ascent: r.y_max as f32 * self.unit,
+                descent: -(r.y_min as f32) * self.unit,
+            })
+        })
+    }
+
+    pub fn italic_correction(&self, gid: GlyphId) -> f32 {
+        self.math
+            .glyph_info
+            .and_then(|gi| gi.italic_corrections)
+            .and_then(|ic| ic.get(ttf_parser::GlyphId(gid.0)))
+            .map_or(0.0, |v| v.value as f32 * self.unit)
+    }
+
+    /// Horizontal accent attachment point, defaulting to the middle of the ink.
+    pub fn top_accent_attachment(&self, gid: GlyphId) -> f32 {
+        self.math
+            .glyph_info
+            .and_then(|gi| gi.top_accent_attachments)
+            .and_then(|ta| ta.get(ttf_parser::GlyphId(gid.0)))
+            .map(|v| v.value as f32 * self.unit)
+            .unwrap_or_else(|| match self.ink(gid) {
+                Some(ink) => (ink.x_min + ink.x_max) / 2.0,
+                None => self.advance(gid) / 2.0,
+            })
+    }
+
+    fn vertical_construction(&self, gid: GlyphId) -> Option<ttf_parser::math::GlyphConstruction<'a>> {
+        self.math
+            .variants
+            .and_then(|v| v.vertical_constructions.get(ttf_parser::GlyphId(gid.0)))
+    }
+
+    fn horizontal_construction(&self, gid: GlyphId) -> Option<ttf_parser::math::GlyphConstruction<'a>> {
+        self.math
+            .variants
+            .and_then(|v| v.horizontal_constructions.get(ttf_parser::GlyphId(gid.0)))
+    }
+
+    /// Smallest vertical size variant of `gid` at least `target` tall, or the
+    /// largest one there is. Never an assembly.
+    pub fn vertical_variant(&self, gid: GlyphId, target: f32) -> GlyphId {
+        let mut best = gid;
+        if let Some(construction) = self.vertical_construction(gid) {
+            for v in construction.variants {
+                best = GlyphId(v.variant_glyph.0);
+                if v.advance_measurement as f32 * self.unit >= target {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Smallest vertical variant of `gid` at least `target` tall, falling back
+    /// to the glyph assembly (or the largest variant) for taller targets.
+    pub fn stretch_vertical(&self, gid: GlyphId, target: f32) -> Stretched {
+        self.stretch(self.vertical_construction(gid), gid, target)
+    }
+
+    /// Smallest horizontal variant of `gid` at least `target` wide, falling
+    /// back to the glyph assembly (or the widest variant) for wider targets.
+    pub fn stretch_horizontal(&self, gid: GlyphId, target: f32) -> Stretched {
+        self.stretch(self.horizontal_construction(gid), gid, target)
+    }
+
+    fn stretch(
+        &self,
+        construction: Option<ttf_parser::math::GlyphConstruction<'a>>,
+        gid: GlyphId,
+        target: f32,
+    ) -> Stretched {
+        let Some(construction) = construction else {
+            return Stretched::Variant(gid);
+        };
+        let mut best = gid;
+        for v in construction.variants {
+            best = GlyphId(v.variant_glyph.0);
+            if v.advance_measurement as f32 * self.unit >= target {
+                return Stretched::Variant(best);
+            }
+        }
+        match construction.assembly {
+            Some(assembly) => self.assemble(assembly, target),
+            None => Stretched::Variant(best),
+        }
+    }
+
+    /// Repeat the extenders of `assembly` until it reaches `target`, then
+    /// spread the connector overlaps evenly to hit the length exactly.
+    fn assemble(&self, assembly: ttf_parser::math::GlyphAssembly, target: f32) -> Stretched {
+        let min_overlap = self
+            .math
+            .variants
+            .map_or(0.0, |v| v.min_connector_overlap as f32 * self.unit);
+        let parts: Vec<_> = assembly.parts.into_iter().collect();
+        let extenders = parts.iter().filter(|p| p.part_flags.extender()).count();
+
+        let mut repeats = 0;
+        let seq = loop {
+            let seq: Vec<_> = parts
+                .iter()
+                .flat_map(|p| {
+                    let n = if p.part_flags.extender() { repeats } else { 1 };
+                    std::iter::repeat_n(*p, n)
+                })
+                .collect();
+            let full: f32 = seq.iter().map(|p| p.full_advance as f32 * self.unit).sum();
+            let joints = seq.len().saturating_sub(1) as f32;
+            if full - joints * min_overlap >= target || extenders == 0 || repeats > 100 {
+                break seq;
+            }
+            repeats += 1;
+        };
+
+        let full: f32 = seq.iter().map(|p| p.full_advance as f32 * self.unit).sum();
+        let joints = seq.len().saturating_sub(1);
+        let max_overlap = seq
+            .windows(2)
+            .map(|w| w[0].end_connector_length.min(w[1].start_connector_length) as f32 * self.unit)
+            .fold(f32::INFINITY, f32::min)
+            .max(min_overlap);
+        let overlap = if joints == 0 {
+            0.0
+        } else {
+            ((full - target) / joints as f32).clamp(min_overlap, max_overlap)
+        };
+
+        let mut offset = 0.0;
+        let mut placed = Vec::with_capacity(seq.len());
+        for (i, p) in seq.iter().enumerate() {
+            if i > 0 {
+                offset -= overlap;
+            }
+            placed.push(PlacedPart { glyph: GlyphId(p.glyph_id.0), offset });
+            offset += p.full_advance as f32 * self.unit;
+        }
+        Stretched::Assembly { parts: placed, length: offset }
+    }
+}
@@ -1,44 +1,398 @@
 /// LaTeX math parser → AST
+use std::fmt;
+use std::ops::Range;
+
+use crate::color;
 
 #[derive(Debug, Clone)]
 pub enum MathNode {
     Symbol(char),
     Row(Vec<MathNode>),
-    Frac(Box<MathNode>, Box<MathNode>),
+    /// Fraction, or a stack without a rule, optionally between delimiters:
+    /// `\frac`, `\binom`, `\genfrac`, `\atop`, ...
+    Frac {
+        num: Box<MathNode>,
+        den: Box<MathNode>,
+        /// Rule thickness in em; `None` uses the font's.
+        rule: Option<f32>,
+        /// Delimiters around the fraction, `'\0'` for none.
+        left: char,
+        right: char,
+        /// Where the numerator sits when the denominator is wider: centred,
+        /// or flush left or right for `\cfrac[l]` and `\cfrac[r]`.
+        num_align: ColumnAlign,
+    },
     Sup(Box<MathNode>, Box<MathNode>),
     Sub(Box<MathNode>, Box<MathNode>),
     SubSup(Box<MathNode>, Box<MathNode>, Box<MathNode>),
-    Sqrt(Box<MathNode>),
+    /// Radical with an optional index (`\sqrt[3]{x}`).
+    Sqrt {
+        index: Option<Box<MathNode>>,
+        radicand: Box<MathNode>,
+    },
     Matrix {
         rows: Vec<Vec<MathNode>>,
         left_delim: Option<char>,
         right_delim: Option<char>,
+        spec: ArraySpec,
     },
     Text(String),
     Space(f32),
+    /// Explicit horizontal space (`\hspace`, `\kern`, `\mkern`, `\mskip`, ...).
+    Kern(Length),
+    /// Takes up the room of its content without drawing it: its width
+    /// (`\hphantom`), its height and depth (`\vphantom`, `\mathstrut`) or both
+    /// (`\phantom`).
+    Phantom {
+        content: Box<MathNode>,
+        width: bool,
+        height: bool,
+    },
+    /// Content drawn as usual but laid out without its height (`top`) and/or
+    /// its depth (`bottom`): `\smash`, `\smash[t]`, `\smash[b]`.
+    Smash {
+        content: Box<MathNode>,
+        top: bool,
+        bottom: bool,
+    },
     Overline(Box<MathNode>),
+    Underline(Box<MathNode>),
+    /// `\left ... \right`; `'\0'` is the null delimiter `.`.
     Delimited {
         left: char,
         right: char,
         content: Box<MathNode>,
+        /// `\middle` delimiters, each with the content that follows it.
+        middle: Vec<(char, MathNode)>,
     },
+    /// Delimiter at one of the fixed sizes of `\big`, `\Big`, `\bigg` and
+    /// `\Bigg` (1 to 4).
+    BigDelim { delim: char, size: u8 },
     Accent(char, Box<MathNode>),
-    Cases(Vec<Vec<MathNode>>),
+    /// Accent stretched to the width of its content (`\widehat`, `\overrightarrow`).
+    WideAccent(char, Box<MathNode>),
+    /// Horizontal brace over or under its content; labels attach as limits.
+    Brace {
+        over: bool,
+        content: Box<MathNode>,
+    },
+    /// `cases` and its variants: left-aligned values and conditions with a
+    /// brace on the left, or on the right for `rcases`.
+    Cases {
+        rows: Vec<Vec<MathNode>>,
+        /// `dcases`: cells in display style rather than text style.
+        display: bool,
+        /// `rcases`: the brace goes on the right.
+        right_brace: bool,
+    },
+    /// Rows of an alignment environment (`align`, `gather`, `multline`, ...).
+    Aligned {
+        kind: AlignKind,
+        rows: Vec<Vec<MathNode>>,
+    },
+    /// Large or named operator (`\sum`, `\int`, `\lim`, `\sin`) with its limits.
+    BigOp {
+        op: Box<MathNode>,
+        limits: Limits,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    /// Content typeset in an explicit style (`\displaystyle`, `\dfrac`, ...).
+    Style(MathStyle, Box<MathNode>),
+    /// Content set in a math alphabet (`\mathbb`, `\mathbf`, ...).
+    Styled(MathVariant, Box<MathNode>),
+    /// Content forced into an atom class (`\mathbin`, `\mathrel`, ...).
+    Class(AtomClass, Box<MathNode>),
+    /// An equation with the tags drawn at its right: one per row of an
+    /// alignment, or a single one for the whole equation. `None` leaves a
+    /// row unnumbered.
+    Tagged {
+        content: Box<MathNode>,
+        tags: Vec<Option<MathNode>>,
+    },
+    /// Content struck through with a slash, for `\not` on a symbol that has
+    /// no precomposed negated form.
+    Negated(Box<MathNode>),
+    /// `\hdotsfor[spacing]{columns}`: a row of dots across matrix columns.
+    HDotsFor {
+        columns: usize,
+        spacing: f32,
+    },
+    /// Arrow stretched to fit a label above it and an optional one below
+    /// (`\xrightarrow[below]{above}`, `\xmapsto`, ...).
+    XArrow {
+        arrow: char,
+        above: Box<MathNode>,
+        below: Option<Box<MathNode>>,
+    },
+    /// Content struck out with diagonal strokes, or with an arrow pointing
+    /// to a value (the `cancel` package).
+    Cancel {
+        content: Box<MathNode>,
+        kind: CancelKind,
+    },
+    /// Content drawn in a color (`\color`, `\textcolor`).
+    Colored(Color, Box<MathNode>),
+    /// Content padded inside a frame and/or on a colored background
+    /// (`\boxed`, `\fbox`, `\colorbox`, `\fcolorbox`).
+    Boxed {
+        content: Box<MathNode>,
+        frame: bool,
+        /// Color of the frame; `None` draws it in the surrounding color.
+        frame_color: Option<Color>,
+        background: Option<Color>,
+    },
+}
+
+/// How an alignment environment lines up its columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignKind {
+    /// `align`, `aligned`, `split`, ...: pairs of right- and left-aligned columns.
+    Align,
+    /// `gather`: every row centred.
+    Gather,
+    /// `multline`: first row flush left, last row flush right, others centred.
+    Multline,
+    /// `eqnarray`: right, centred and left-aligned columns.
+    EqnArray,
+}
+
+/// Column layout and rules of a matrix or `array`. An empty spec (the
+/// `matrix` family) centres every column and leaves no space at the edges.
+#[derive(Debug, Clone, Default)]
+pub struct ArraySpec {
+    /// Alignment of each column from `{lcr}`; missing columns are centred.
+    pub columns: Vec<ColumnAlign>,
+    /// What sits at each column boundary, from left of the first column to
+    /// right of the last: one more entry than `columns`.
+    pub boundaries: Vec<Boundary>,
+    /// Horizontal rules above each row, then below the last one.
+    pub hlines: Vec<Vec<HRule>>,
+    /// `smallmatrix`: cells in script style, columns a thick space apart.
+    pub small: bool,
+    /// `\arraystretch`, scaling the row height; `None` keeps the default.
+    pub stretch: Option<f32>,
+    /// `\arraycolsep` in em, padding each side of a column; `None` keeps the default.
+    pub column_sep: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// Vertical rules (`|`, `||`) and `@{..}` material between two columns.
+#[derive(Debug, Clone, Default)]
+pub struct Boundary {
+    pub rules: usize,
+    /// `@{..}`, which also removes the column padding on both sides.
+    pub separator: Option<MathNode>,
+}
+
+/// A horizontal rule between rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HRule {
+    /// `\hline`, across the whole array.
+    Full,
+    /// `\cline{i-j}`, over a range of columns (counted from 0 here).
+    Columns(Range<usize>),
+}
+
+/// The strokes of a [`MathNode::Cancel`].
+#[derive(Debug, Clone)]
+pub enum CancelKind {
+    /// `\cancel`: from bottom left to top right.
+    Forward,
+    /// `\bcancel`: from top left to bottom right.
+    Back,
+    /// `\xcancel`: both.
+    Cross,
+    /// `\cancelto{value}`: an arrow from bottom left up past the top right,
+    /// with the value at its tip.
+    To(Box<MathNode>),
+}
+
+/// TeX atom class, which decides the spacing between neighbouring atoms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtomClass {
+    Ord,
+    Op,
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+    Inner,
 }
 
+impl MathNode {
+    /// Atom class of this node in a row, or `None` for spacing, which does
+    /// not take part in inter-atom spacing.
+    pub fn atom_class(&self) -> Option<AtomClass> {
+        let class = match self {
+            MathNode::Space(_) | MathNode::Kern(_) => return None,
+            MathNode::Symbol(ch) => char_class(*ch),
+            MathNode::Sup(base, _) | MathNode::Sub(base, _) | MathNode::SubSup(base, _, _) => {
+                return base.atom_class();
+            }
+            MathNode::BigOp { .. } => AtomClass::Op,
+            MathNode::Frac { .. } | MathNode::Delimited { .. } | MathNode::Cases { .. } => AtomClass::Inner,
+            MathNode::Matrix { left_delim, right_delim, .. } => {
+                if left_delim.is_some() || right_delim.is_some() { AtomClass::Inner } else { AtomClass::Ord }
+            }
+            MathNode::Style(_, content)
+            | MathNode::Styled(_, content)
+            | MathNode::Negated(content)
+            | MathNode::Colored(_, content) => {
+                return content.atom_class();
+            }
+            MathNode::Class(class, _) => *class,
+            _ => AtomClass::Ord,
+        };
+        Some(class)
+    }
+}
+
+/// Math alphabet of a [`MathNode::Styled`] group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathVariant {
+    Normal,
+    Bold,
+    Italic,
+    BoldItalic,
+    Script,
+    BoldScript,
+    Fraktur,
+    BoldFraktur,
+    DoubleStruck,
+    SansSerif,
+    SansSerifBold,
+    SansSerifItalic,
+    Monospace,
+}
+
+/// A length from the source, in em. Font-relative units (`em`, `ex`, `mu`)
+/// follow the size of the current style; the others are fixed, and measured
+/// against the text size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Relative(f32),
+    Absolute(f32),
+}
+
+/// An RGB color, from a name, a color model or a mix (see [`MathNode::Colored`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub [u8; 3]);
+
+/// TeX math style; each step down shrinks scripts and tightens spacing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MathStyle {
+    Display,
+    Text,
+    Script,
+    ScriptScript,
+}
+
+/// Where a big operator's scripts go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limits {
+    /// Above and below in display style, at the side inline (`\sum`, `\lim`).
+    Display,
+    /// Always above and below (`\limits`).
+    Always,
+    /// Always at the side (`\nolimits`, integrals, `\sin`).
+    Never,
+}
+
+/// What went wrong in a [`ParseError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A `{` without its `}`, or a `}` without its `{`.
+    UnbalancedBrace,
+    /// A command or script with nothing to apply to (`\frac{a}`, `x^`).
+    MissingArgument,
+    /// `\left` without `\right`.
+    MissingRight,
+    /// `\right` or `\middle` without `\left`.
+    UnmatchedRight,
+    /// Something after `\left`/`\right` that is not a delimiter.
+    BadDelimiter,
+    UnknownEnvironment,
+    /// `\begin{..}` without `\end{..}`.
+    MissingEnd,
+    /// `\end{b}` closing a `\begin{a}`.
+    MismatchedEnd,
+    /// `\end{..}` without `\begin{..}`.
+    UnmatchedEnd,
+    UnknownCommand,
+    /// A color name, model or value that xcolor would not accept.
+    UnknownColor,
+    /// A length without a number or with an unknown unit (`\hspace{2}`).
+    BadLength,
+    /// `x^a^b` or `x_a_b`.
+    DoubleScript,
+    /// `&` or `\\` outside an environment.
+    MisplacedAlignment,
+}
+
+/// A problem found while parsing, with the byte range of the input it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 struct Parser {
     chars: Vec<char>,
     pos: usize,
+    /// Byte offset of each char in the original input, plus the end.
+    offsets: Vec<usize>,
+    errors: Vec<ParseError>,
+    /// `\arraystretch` and `\arraycolsep` set so far in the current group.
+    array_stretch: Option<f32>,
+    array_column_sep: Option<f32>,
 }
 
 impl Parser {
-    fn new(input: &str) -> Self {
+    fn new(input: &str, base: usize) -> Self {
+        let mut offsets: Vec<usize> = input.char_indices().map(|(i, _)| base + i).collect();
+        offsets.push(base + input.len());
         Parser {
             chars: input.chars().collect(),
             pos: 0,
+            offsets,
+            errors: Vec::new(),
+            array_stretch: None,
+            array_column_sep: None,
         }
     }
 
+    /// Record a problem covering chars `start..end`; parsing carries on.
+    fn error(&mut self, kind: ParseErrorKind, start: usize, end: usize, message: impl Into<String>) {
+        let end = end.clamp(start, self.chars.len());
+        self.errors.push(ParseError {
+            kind,
+            span: self.offsets[start.min(self.chars.len())]..self.offsets[end],
+            message: message.into(),
+        });
+    }
+
+    /// Position of the backslash of `cmd`, which has just been read.
+    fn cmd_start(&self, cmd: &str) -> usize {
+        self.pos.saturating_sub(cmd.chars().count() + 1)
+    }
+
     fn peek(&self) -> Option<char> {
         self.chars.get(self.pos).copied()
     }
@@ -97,6 +451,48 @@ impl Parser {
         cmd
     }
 
+    /// Consume `\name` if it comes next (and is not a prefix of a longer command).
+    fn eat_cmd(&mut self, name: &str) -> bool {
+        self.skip_ws();
+        let saved = self.pos;
+        if self.peek() == Some('\\') {
+            self.advance();
+            if self.read_cmd() == name {
+                return true;
+            }
+        }
+        self.pos = saved;
+        false
+    }
+
+    /// Unparsed contents of a balanced `{..}` group, if one comes next.
+    fn read_raw_group(&mut self) -> String {
+        let mut text = String::new();
+        if !self.eat('{') {
+            return text;
+        }
+        let mut depth = 0;
+        while let Some(c) = self.advance() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                '\\' => {
+                    // Keep escaped braces from counting.
+                    text.push(c);
+                    match self.advance() {
+                        Some(next) => text.push(next),
+                        None => break,
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        text
+    }
+
     fn read_env_name(&mut self) -> String {
         self.eat('{');
         let name = self.read_until('}');
@@ -106,13 +502,20 @@ impl Parser {
 
     fn read_group(&mut self) -> MathNode {
         self.skip_ws();
+        let start = self.pos;
         if self.eat('{') {
+            let saved = (self.array_stretch, self.array_column_sep);
             let node = self.parse_expr_until(|c| c == '}');
-            self.eat('}');
+            if !self.eat('}') {
+                self.error(ParseErrorKind::UnbalancedBrace, start, start + 1, "missing `}` for this `{`");
+            }
+            (self.array_stretch, self.array_column_sep) = saved;
             node
         } else {
-            self.parse_single_atom()
-                .unwrap_or(MathNode::Row(vec![]))
+            self.parse_single_atom().unwrap_or_else(|| {
+                self.error(ParseErrorKind::MissingArgument, start, self.pos, "missing argument");
+                MathNode::Row(vec![])
+            })
         }
     }
 
@@ -130,6 +533,13 @@ impl Parser {
             '}' | '&' | '^' | '_' => None,
             c if is_math_char(c) => {
                 self.advance();
+                // ASCII hyphen and asterisk stand for the minus sign and the
+                // centred asterisk operator.
+                let c = match c {
+                    '-' => '\u{2212}',
+                    '*' => '\u{2217}',
+                    c => c,
+                };
                 Some(MathNode::Symbol(c))
             }
             _ => {
@@ -139,9 +549,116 @@ impl Parser {
         }
     }
 
+    /// Skip `\tag{..}`, `\notag`, `\nonumber` and `\label{..}`, which only
+    /// matter to equation numbering (see `numbering`), not to the layout.
+    fn skip_numbering_marker(&mut self, cmd: &str) -> bool {
+        match cmd {
+            "notag" | "nonumber" => {}
+            "tag" | "label" => {
+                self.eat('*');
+                self.read_raw_group();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Apply `\renewcommand{\arraystretch}{1.5}`, `\def\arraystretch{..}`,
+    /// `\setlength{\arraycolsep}{2pt}` or `\arraycolsep=2pt` to the arrays
+    /// that follow in the group.
+    fn set_array_parameter(&mut self, cmd: &str) -> bool {
+        let saved = self.pos;
+        match cmd {
+            "renewcommand" | "def" => {
+                let open = self.eat('{');
+                if self.eat_cmd("arraystretch") && (!open || self.eat('}')) && self.eat('{') {
+                    let value = self.read_until('}');
+                    self.eat('}');
+                    self.array_stretch = value.trim().parse().ok();
+                    return true;
+                }
+            }
+            "setlength" | "arraycolsep" => {
+                let open = cmd == "setlength" && self.eat('{');
+                if cmd == "arraycolsep" || (self.eat_cmd("arraycolsep") && (!open || self.eat('}'))) {
+                    self.eat('=');
+                    self.array_column_sep = self.read_length();
+                    return true;
+                }
+            }
+            _ => return false,
+        }
+        self.pos = saved;
+        false
+    }
+
+    /// A length, braced or bare (a number and a two-letter unit: `2pt`,
+    /// `0.5em`), in em.
+    fn read_length(&mut self) -> Option<f32> {
+        length_em(&self.read_length_text())
+    }
+
+    /// The space of `\hspace`, `\kern`, `\mskip`, ..., for the command
+    /// starting at `start`. Stretch and shrink (`plus 1fill`) are dropped.
+    fn read_glue(&mut self, start: usize) -> Option<Length> {
+        let text = self.read_length_text();
+        let natural = text.split(" plus").next().unwrap_or_default().split(" minus").next().unwrap_or_default();
+        for word in ["plus", "minus"] {
+            if self.eat_word(word) {
+                self.read_length_text();
+            }
+        }
+        let glue = length(natural);
+        if glue.is_none() {
+            self.error(ParseErrorKind::BadLength, start, self.pos, format!("`{}` is not a length", text.trim()));
+        }
+        glue
+    }
+
+    /// Skip `word` if it comes next (after whitespace) as a whole word.
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.skip_ws();
+        let end = self.pos + word.chars().count();
+        let matches = end <= self.chars.len()
+            && self.chars[self.pos..end].iter().copied().eq(word.chars())
+            && !self.chars.get(end).is_some_and(char::is_ascii_alphabetic);
+        if matches {
+            self.pos = end;
+        }
+        matches
+    }
+
+    fn read_length_text(&mut self) -> String {
+        if self.eat('{') {
+            let value = self.read_until('}');
+            self.eat('}');
+            value
+        } else {
+            self.skip_ws();
+            let mut value = String::new();
+            while let Some(ch) = self.peek().filter(|c| c.is_ascii_digit() || matches!(c, '.' | '-')) {
+                value.push(ch);
+                self.advance();
+            }
+            self.skip_ws();
+            for _ in 0..2 {
+                if let Some(ch) = self.peek().filter(char::is_ascii_alphabetic) {
+                    value.push(ch);
+                    self.advance();
+                }
+            }
+            value
+        }
+    }
+
     /// Parse expression: sequence of atoms with scripts attached.
     fn parse_expr_until(&mut self, stop: impl Fn(char) -> bool) -> MathNode {
         let mut nodes = Vec::new();
+        // Style and color switches seen so far and where in `nodes` each one starts.
+        let mut styles: Vec<(Switch, usize)> = Vec::new();
+        // Numerator of an infix `\over`, `\atop`, `\choose`, ... and how
+        // to set the fraction.
+        let mut infix: Option<(MathNode, InfixFrac)> = None;
 
         loop {
             self.skip_ws();
@@ -153,10 +670,32 @@ impl Parser {
                     let saved = self.pos;
                     self.advance();
                     let cmd = self.read_cmd();
-                    if cmd == "end" || cmd == "right" || cmd == "\\" {
+                    if cmd == "end" || cmd == "right" || cmd == "middle" || cmd == "\\" {
                         self.pos = saved;
                         break;
                     }
+                    // Style switches apply to the rest of the group.
+                    if let Some(style) = style_cmd(&cmd) {
+                        styles.push((Switch::Style(style), nodes.len()));
+                        continue;
+                    }
+                    if cmd == "color" {
+                        if let Some(color) = self.read_color(saved) {
+                            styles.push((Switch::Color(color), nodes.len()));
+                        }
+                        continue;
+                    }
+                    if self.skip_numbering_marker(&cmd) || self.set_array_parameter(&cmd) {
+                        continue;
+                    }
+                    if let Some(mut kind) = infix_frac(&cmd) {
+                        if cmd == "above" {
+                            kind.0 = self.read_length();
+                        }
+                        let num = with_styles(std::mem::take(&mut nodes), std::mem::take(&mut styles));
+                        infix = Some((num, kind));
+                        continue;
+                    }
                     self.pos = saved;
                     match self.parse_single_atom() {
                         Some(n) => nodes.push(self.maybe_scripts(n)),
@@ -175,15 +714,26 @@ impl Parser {
             }
         }
 
-        if nodes.len() == 1 {
-            nodes.pop().unwrap()
-        } else {
-            MathNode::Row(nodes)
+        let body = with_styles(nodes, styles);
+        match infix {
+            Some((num, (rule, left, right))) => fraction(num, body, rule, left, right),
+            None => body,
         }
     }
 
     /// After parsing an atom, check for ^ and _ to attach scripts.
-    fn maybe_scripts(&mut self, base: MathNode) -> MathNode {
+    fn maybe_scripts(&mut self, mut base: MathNode) -> MathNode {
+        if let MathNode::BigOp { limits, .. } = &mut base {
+            loop {
+                if self.eat_cmd("limits") {
+                    *limits = Limits::Always;
+                } else if self.eat_cmd("nolimits") {
+                    *limits = Limits::Never;
+                } else {
+                    break;
+                }
+            }
+        }
         let mut sup = None;
         let mut sub = None;
         loop {
@@ -197,9 +747,20 @@ impl Parser {
                     self.advance();
                     sub = Some(Box::new(self.read_group()));
                 }
+                Some('^') => {
+                    self.error(ParseErrorKind::DoubleScript, self.pos, self.pos + 1, "double superscript");
+                    break;
+                }
+                Some('_') => {
+                    self.error(ParseErrorKind::DoubleScript, self.pos, self.pos + 1, "double subscript");
+                    break;
+                }
                 _ => break,
             }
         }
+        if let MathNode::BigOp { op, limits, .. } = base {
+            return MathNode::BigOp { op, limits, sub, sup };
+        }
         match (sub, sup) {
             (Some(sb), Some(sp)) => MathNode::SubSup(Box::new(base), sb, sp),
             (None, Some(sp)) => MathNode::Sup(Box::new(base), sp),
@@ -211,36 +772,236 @@ impl Parser {
     fn dispatch_cmd(&mut self, cmd: &str) -> Option<MathNode> {
         match cmd {
             // Fractions
-            "frac" | "dfrac" | "tfrac" => {
+            "frac" | "dfrac" | "tfrac" | "binom" | "dbinom" | "tbinom" => {
+                let num = self.read_group();
+                let den = self.read_group();
+                let frac = if cmd.ends_with("binom") {
+                    fraction(num, den, Some(0.0), '(', ')')
+                } else {
+                    fraction(num, den, None, '\0', '\0')
+                };
+                Some(match cmd.chars().next() {
+                    Some('d') => MathNode::Style(MathStyle::Display, Box::new(frac)),
+                    Some('t') => MathNode::Style(MathStyle::Text, Box::new(frac)),
+                    _ => frac,
+                })
+            }
+            "genfrac" => {
+                let mut delim = || {
+                    self.eat('{');
+                    let ch = if self.peek() == Some('}') { '\0' } else { self.read_delim_char() };
+                    self.eat('}');
+                    ch
+                };
+                let (left, right) = (delim(), delim());
+                let rule = self.read_raw_group();
+                let rule = if rule.trim().is_empty() { None } else { length_em(&rule) };
+                let style = match self.read_raw_group().trim() {
+                    "0" => Some(MathStyle::Display),
+                    "1" => Some(MathStyle::Text),
+                    "2" => Some(MathStyle::Script),
+                    "3" => Some(MathStyle::ScriptScript),
+                    _ => None,
+                };
                 let num = self.read_group();
                 let den = self.read_group();
-                Some(MathNode::Frac(Box::new(num), Box::new(den)))
+                let frac = fraction(num, den, rule, left, right);
+                Some(match style {
+                    Some(style) => MathNode::Style(style, Box::new(frac)),
+                    None => frac,
+                })
+            }
+            // Continued fractions keep display style at every level; `[l]`
+            // and `[r]` push the numerator to one side.
+            "cfrac" => {
+                let mut num_align = ColumnAlign::Center;
+                if self.eat('[') {
+                    num_align = match self.read_until(']').trim() {
+                        "l" => ColumnAlign::Left,
+                        "r" => ColumnAlign::Right,
+                        _ => ColumnAlign::Center,
+                    };
+                    self.eat(']');
+                }
+                let num = MathNode::Style(MathStyle::Display, Box::new(self.read_group()));
+                let den = MathNode::Style(MathStyle::Display, Box::new(self.read_group()));
+                let frac = MathNode::Frac {
+                    num: Box::new(num),
+                    den: Box::new(den),
+                    rule: None,
+                    left: '\0',
+                    right: '\0',
+                    num_align,
+                };
+                Some(MathNode::Style(MathStyle::Display, Box::new(frac)))
+            }
+            "substack" => {
+                let start = self.cmd_start(cmd);
+                let mut rows = Vec::new();
+                if self.eat('{') {
+                    loop {
+                        rows.push(vec![self.parse_expr_until(|c| c == '}')]);
+                        if !self.eat_cmd("\\") {
+                            break;
+                        }
+                    }
+                    if !self.eat('}') {
+                        self.error(ParseErrorKind::UnbalancedBrace, start, self.pos, "missing `}` for `\\substack`");
+                    }
+                }
+                Some(substack(rows, ColumnAlign::Center))
             }
             "sqrt" => {
-                let content = self.read_group();
-                Some(MathNode::Sqrt(Box::new(content)))
+                let index = if self.eat('[') {
+                    let start = self.pos - 1;
+                    let index = self.parse_expr_until(|c| c == ']');
+                    if !self.eat(']') {
+                        self.error(ParseErrorKind::UnbalancedBrace, start, start + 1, "missing `]` for this `[`");
+                    }
+                    Some(Box::new(index))
+                } else {
+                    None
+                };
+                let radicand = self.read_group();
+                Some(MathNode::Sqrt { index, radicand: Box::new(radicand) })
+            }
+            _ if xarrow_char(cmd).is_some() => {
+                let arrow = xarrow_char(cmd)?;
+                let below = if self.eat('[') {
+                    let start = self.pos - 1;
+                    let below = self.parse_expr_until(|c| c == ']');
+                    if !self.eat(']') {
+                        self.error(ParseErrorKind::UnbalancedBrace, start, start + 1, "missing `]` for this `[`");
+                    }
+                    Some(Box::new(below))
+                } else {
+                    None
+                };
+                let above = Box::new(self.read_group());
+                Some(MathNode::Class(AtomClass::Rel, Box::new(MathNode::XArrow { arrow, above, below })))
+            }
+            "cancel" | "bcancel" | "xcancel" | "cancelto" => {
+                let kind = match cmd {
+                    "cancel" => CancelKind::Forward,
+                    "bcancel" => CancelKind::Back,
+                    "xcancel" => CancelKind::Cross,
+                    _ => CancelKind::To(Box::new(self.read_group())),
+                };
+                let content = Box::new(self.read_group());
+                Some(MathNode::Cancel { content, kind })
             }
             "overline" | "bar" => {
                 let c = self.read_group();
                 Some(MathNode::Overline(Box::new(c)))
             }
             "hat" => { let c = self.read_group(); Some(MathNode::Accent('\u{0302}', Box::new(c))) }
-            "tilde" => { let c = self.read_group(); Some(MathNode::Accent('~', Box::new(c))) }
-            "vec" => { let c = self.read_group(); Some(MathNode::Accent('\u{2192}', Box::new(c))) }
-            "dot" => { let c = self.read_group(); Some(MathNode::Accent('\u{02D9}', Box::new(c))) }
-            "ddot" => { let c = self.read_group(); Some(MathNode::Accent('\u{00A8}', Box::new(c))) }
+            "tilde" => { let c = self.read_group(); Some(MathNode::Accent('\u{0303}', Box::new(c))) }
+            "vec" => { let c = self.read_group(); Some(MathNode::Accent('\u{20D7}', Box::new(c))) }
+            "dot" => { let c = self.read_group(); Some(MathNode::Accent('\u{0307}', Box::new(c))) }
+            "ddot" => { let c = self.read_group(); Some(MathNode::Accent('\u{0308}', Box::new(c))) }
+            "dddot" => { let c = self.read_group(); Some(MathNode::Accent('\u{20DB}', Box::new(c))) }
+            "check" => { let c = self.read_group(); Some(MathNode::Accent('\u{030C}', Box::new(c))) }
+            "breve" => { let c = self.read_group(); Some(MathNode::Accent('\u{0306}', Box::new(c))) }
+            "acute" => { let c = self.read_group(); Some(MathNode::Accent('\u{0301}', Box::new(c))) }
+            "grave" => { let c = self.read_group(); Some(MathNode::Accent('\u{0300}', Box::new(c))) }
+            "mathring" => { let c = self.read_group(); Some(MathNode::Accent('\u{030A}', Box::new(c))) }
+            "widehat" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{0302}', Box::new(c))) }
+            "widetilde" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{0303}', Box::new(c))) }
+            "widecheck" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{030C}', Box::new(c))) }
+            "overrightarrow" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{20D7}', Box::new(c))) }
+            "overleftarrow" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{20D6}', Box::new(c))) }
+            "overleftrightarrow" => { let c = self.read_group(); Some(MathNode::WideAccent('\u{20E1}', Box::new(c))) }
+            "underline" => {
+                let c = self.read_group();
+                Some(MathNode::Underline(Box::new(c)))
+            }
+            "overbrace" | "underbrace" => {
+                let c = self.read_group();
+                let brace = MathNode::Brace { over: cmd == "overbrace", content: Box::new(c) };
+                Some(MathNode::BigOp { op: Box::new(brace), limits: Limits::Always, sub: None, sup: None })
+            }
+            "overset" | "underset" | "stackrel" => {
+                let script = Box::new(self.read_group());
+                let base = self.read_group();
+                let class = match cmd {
+                    "stackrel" => AtomClass::Rel,
+                    _ => base.atom_class().unwrap_or(AtomClass::Ord),
+                };
+                let (sub, sup) = if cmd == "underset" { (Some(script), None) } else { (None, Some(script)) };
+                // A row keeps a single symbol from being set as a large operator.
+                let op = Box::new(MathNode::Row(vec![base]));
+                let stack = MathNode::BigOp { op, limits: Limits::Always, sub, sup };
+                Some(MathNode::Class(class, Box::new(stack)))
+            }
 
             // Text
-            "text" | "textrm" | "mathrm" | "operatorname" => {
+            "text" | "textrm" => Some(self.read_text()),
+            "textcolor" => {
+                let color = self.read_color(self.cmd_start(cmd));
+                let content = self.read_group();
+                Some(match color {
+                    Some(color) => MathNode::Colored(color, Box::new(content)),
+                    None => content,
+                })
+            }
+            "boxed" | "fbox" | "colorbox" | "fcolorbox" => {
+                let start = self.cmd_start(cmd);
+                let frame_color = if cmd == "fcolorbox" { self.read_color(start) } else { None };
+                let background = if cmd.ends_with("colorbox") { self.read_color(start) } else { None };
+                // `\boxed` frames display math; the others take text.
+                let content = if cmd == "boxed" {
+                    MathNode::Style(MathStyle::Display, Box::new(self.read_group()))
+                } else {
+                    self.read_text()
+                };
+                Some(MathNode::Boxed { content: Box::new(content), frame: cmd != "colorbox", frame_color, background })
+            }
+            "operatorname" => {
+                let limits = if self.eat('*') { Limits::Display } else { Limits::Never };
                 self.eat('{');
                 let t = self.read_until('}');
                 self.eat('}');
-                Some(MathNode::Text(t))
+                // Names like `arg\,max` space their words with a thin space.
+                named_op(&t.replace("\\,", "\u{2009}").replace("\\ ", " "), limits)
+            }
+            "mathrm" => {
+                let content = self.read_group();
+                Some(MathNode::Styled(MathVariant::Normal, Box::new(content)))
+            }
+
+            // Explicit atom classes
+            "mathop" => {
+                let content = self.read_group();
+                Some(MathNode::BigOp { op: Box::new(content), limits: Limits::Display, sub: None, sup: None })
+            }
+            "mathord" | "mathbin" | "mathrel" | "mathopen" | "mathclose" | "mathpunct" | "mathinner" => {
+                let class = match cmd {
+                    "mathord" => AtomClass::Ord,
+                    "mathbin" => AtomClass::Bin,
+                    "mathrel" => AtomClass::Rel,
+                    "mathopen" => AtomClass::Open,
+                    "mathclose" => AtomClass::Close,
+                    "mathpunct" => AtomClass::Punct,
+                    _ => AtomClass::Inner,
+                };
+                let content = self.read_group();
+                Some(MathNode::Class(class, Box::new(content)))
             }
             "mathbf" | "textbf" | "boldsymbol" | "bm"
             | "mathit" | "textit"
-            | "mathcal" | "mathbb" | "mathfrak" | "mathsf" | "mathtt" => {
-                Some(self.read_group())
+            | "mathcal" | "mathscr" | "mathbb" | "mathfrak" | "mathsf" | "mathtt" => {
+                let variant = match cmd {
+                    "mathbf" | "textbf" => MathVariant::Bold,
+                    "boldsymbol" | "bm" => MathVariant::BoldItalic,
+                    "mathit" | "textit" => MathVariant::Italic,
+                    "mathcal" | "mathscr" => MathVariant::Script,
+                    "mathbb" => MathVariant::DoubleStruck,
+                    "mathfrak" => MathVariant::Fraktur,
+                    "mathsf" => MathVariant::SansSerif,
+                    _ => MathVariant::Monospace,
+                };
+                let content = self.read_group();
+                Some(MathNode::Styled(variant, Box::new(content)))
             }
 
             // Greek lowercase
@@ -269,12 +1030,22 @@ impl Parser {
             "Omega" => sym('\u{03A9}'),
 
             // Big operators
-            "sum" => sym('\u{2211}'), "prod" => sym('\u{220F}'),
-            "int" => sym('\u{222B}'), "iint" => sym('\u{222C}'),
-            "iiint" => sym('\u{222D}'), "oint" => sym('\u{222E}'),
-            "bigcup" => sym('\u{22C3}'), "bigcap" => sym('\u{22C2}'),
-            "bigoplus" => sym('\u{2A01}'), "bigotimes" => sym('\u{2A02}'),
-            "coprod" => sym('\u{2210}'),
+            "sum" => big_op('\u{2211}', Limits::Display),
+            "prod" => big_op('\u{220F}', Limits::Display),
+            "coprod" => big_op('\u{2210}', Limits::Display),
+            "bigcup" => big_op('\u{22C3}', Limits::Display),
+            "bigcap" => big_op('\u{22C2}', Limits::Display),
+            "bigvee" => big_op('\u{22C1}', Limits::Display),
+            "bigwedge" => big_op('\u{22C0}', Limits::Display),
+            "bigoplus" => big_op('\u{2A01}', Limits::Display),
+            "bigotimes" => big_op('\u{2A02}', Limits::Display),
+            "bigodot" => big_op('\u{2A00}', Limits::Display),
+            "biguplus" => big_op('\u{2A04}', Limits::Display),
+            "bigsqcup" => big_op('\u{2A06}', Limits::Display),
+            "int" => big_op('\u{222B}', Limits::Never),
+            "iint" => big_op('\u{222C}', Limits::Never),
+            "iiint" => big_op('\u{222D}', Limits::Never),
+            "oint" => big_op('\u{222E}', Limits::Never),
 
             // Binary operators
             "times" => sym('\u{00D7}'), "div" => sym('\u{00F7}'),
@@ -288,7 +1059,7 @@ impl Parser {
             "neq" | "ne" => sym('\u{2260}'), "approx" => sym('\u{2248}'),
             "equiv" => sym('\u{2261}'), "sim" => sym('\u{223C}'),
             "simeq" => sym('\u{2243}'), "cong" => sym('\u{2245}'),
-            "propto" => sym('\u{221D}'),
+            "propto" => sym('\u{221D}'), "asymp" => sym('\u{224D}'),
             "subset" => sym('\u{2282}'), "supset" => sym('\u{2283}'),
             "subseteq" => sym('\u{2286}'), "supseteq" => sym('\u{2287}'),
             "in" => sym('\u{2208}'), "notin" => sym('\u{2209}'),
@@ -296,8 +1067,30 @@ impl Parser {
             "cup" => sym('\u{222A}'), "cap" => sym('\u{2229}'),
             "vee" | "lor" => sym('\u{2228}'), "wedge" | "land" => sym('\u{2227}'),
             "perp" => sym('\u{22A5}'), "parallel" => sym('\u{2225}'),
-            "mid" => sym('|'), "ll" => sym('\u{226A}'), "gg" => sym('\u{226B}'),
+            "mid" => sym('\u{2223}'), "ll" => sym('\u{226A}'), "gg" => sym('\u{226B}'),
             "prec" => sym('\u{227A}'), "succ" => sym('\u{227B}'),
+            "preceq" => sym('\u{2AAF}'), "succeq" => sym('\u{2AB0}'),
+            "vdash" => sym('\u{22A2}'), "models" => sym('\u{22A8}'),
+
+            // Negated relations
+            "nless" => sym('\u{226E}'), "ngtr" => sym('\u{226F}'),
+            "nleq" => sym('\u{2270}'), "ngeq" => sym('\u{2271}'),
+            "nsim" => sym('\u{2241}'), "ncong" => sym('\u{2247}'),
+            "napprox" => sym('\u{2249}'), "nequiv" => sym('\u{2262}'),
+            "nsubset" => sym('\u{2284}'), "nsupset" => sym('\u{2285}'),
+            "nsubseteq" => sym('\u{2288}'), "nsupseteq" => sym('\u{2289}'),
+            "subsetneq" => sym('\u{228A}'), "supsetneq" => sym('\u{228B}'),
+            "nmid" => sym('\u{2224}'), "nparallel" => sym('\u{2226}'),
+            "nprec" => sym('\u{2280}'), "nsucc" => sym('\u{2281}'),
+            "notni" | "nni" => sym('\u{220C}'),
+            "nvdash" => sym('\u{22AC}'), "nvDash" => sym('\u{22AD}'),
+            "nVdash" => sym('\u{22AE}'), "nVDash" => sym('\u{22AF}'),
+            "ntriangleleft" => sym('\u{22EA}'), "ntriangleright" => sym('\u{22EB}'),
+            "ntrianglelefteq" => sym('\u{22EC}'), "ntrianglerighteq" => sym('\u{22ED}'),
+            "nleftarrow" => sym('\u{219A}'), "nrightarrow" => sym('\u{219B}'),
+            "nleftrightarrow" => sym('\u{21AE}'),
+            "nLeftarrow" => sym('\u{21CD}'), "nRightarrow" => sym('\u{21CF}'),
+            "nLeftrightarrow" => sym('\u{21CE}'),
 
             // Arrows
             "to" | "rightarrow" => sym('\u{2192}'),
@@ -307,6 +1100,9 @@ impl Parser {
             "Leftarrow" => sym('\u{21D0}'),
             "Leftrightarrow" | "iff" => sym('\u{21D4}'),
             "uparrow" => sym('\u{2191}'), "downarrow" => sym('\u{2193}'),
+            "updownarrow" => sym('\u{2195}'),
+            "Uparrow" => sym('\u{21D1}'), "Downarrow" => sym('\u{21D3}'),
+            "Updownarrow" => sym('\u{21D5}'),
             "mapsto" => sym('\u{21A6}'),
             "hookrightarrow" => sym('\u{21AA}'),
             "longrightarrow" => sym('\u{27F6}'),
@@ -322,62 +1118,145 @@ impl Parser {
             "prime" => sym('\u{2032}'), "hbar" => sym('\u{210F}'),
             "ell" => sym('\u{2113}'), "aleph" => sym('\u{2135}'),
             "Re" => sym('\u{211C}'), "Im" => sym('\u{2111}'),
+            "imath" => sym('\u{1D6A4}'), "jmath" => sym('\u{1D6A5}'),
 
             // Dots
             "ldots" | "dots" => sym('\u{2026}'),
             "cdots" => sym('\u{22EF}'),
             "vdots" => sym('\u{22EE}'),
             "ddots" => sym('\u{22F1}'),
+            "iddots" | "adots" => sym('\u{22F0}'),
+            "hdotsfor" => {
+                let mut spacing = 1.0;
+                self.skip_ws();
+                if self.peek() == Some('[') {
+                    self.advance();
+                    spacing = self.read_until(']').trim().parse().unwrap_or(1.0);
+                    self.eat(']');
+                }
+                self.eat('{');
+                let columns = self.read_until('}').trim().parse().unwrap_or(1);
+                self.eat('}');
+                Some(MathNode::HDotsFor { columns, spacing })
+            }
 
             // Delimiters
             "langle" => sym('\u{27E8}'), "rangle" => sym('\u{27E9}'),
             "lceil" => sym('\u{2308}'), "rceil" => sym('\u{2309}'),
             "lfloor" => sym('\u{230A}'), "rfloor" => sym('\u{230B}'),
             "lbrace" | "{" => sym('{'), "rbrace" | "}" => sym('}'),
-            "lvert" | "rvert" => sym('|'),
-            "lVert" | "rVert" | "|" => sym('\u{2016}'),
+            "lbrack" => sym('['), "rbrack" => sym(']'),
+            "lgroup" => sym('\u{27EE}'), "rgroup" => sym('\u{27EF}'),
+            "llbracket" => sym('\u{27E6}'), "rrbracket" => sym('\u{27E7}'),
+            "ulcorner" => sym('\u{231C}'), "urcorner" => sym('\u{231D}'),
+            "llcorner" => sym('\u{231E}'), "lrcorner" => sym('\u{231F}'),
+            "vert" | "lvert" | "rvert" => sym('|'),
+            "Vert" | "lVert" | "rVert" | "|" => sym('\u{2016}'),
+            "backslash" => sym('\\'),
+
+            // Escaped specials
+            "#" | "%" | "&" | "$" | "_" => sym(cmd.chars().next()?),
 
             // Spacing
-            "," => Some(MathNode::Space(0.17)),
-            ":" | ">" => Some(MathNode::Space(0.22)),
-            ";" => Some(MathNode::Space(0.28)),
-            "!" => Some(MathNode::Space(-0.17)),
+            "," | "thinspace" => Some(MathNode::Space(0.17)),
+            ":" | ">" | "medspace" => Some(MathNode::Space(0.22)),
+            ";" | "thickspace" => Some(MathNode::Space(0.28)),
+            "!" | "negthinspace" => Some(MathNode::Space(-0.17)),
+            "negmedspace" => Some(MathNode::Space(-0.22)),
+            "negthickspace" => Some(MathNode::Space(-0.28)),
+            "enspace" | "enskip" => Some(MathNode::Space(0.5)),
             "quad" => Some(MathNode::Space(1.0)),
             "qquad" => Some(MathNode::Space(2.0)),
             " " => Some(MathNode::Space(0.25)),
+            "hspace" | "kern" | "mkern" | "hskip" | "mskip" | "mspace" => {
+                let start = self.cmd_start(cmd);
+                if cmd == "hspace" {
+                    self.eat('*');
+                }
+                Some(MathNode::Kern(self.read_glue(start).unwrap_or(Length::Relative(0.0))))
+            }
+            "phantom" | "hphantom" | "vphantom" => {
+                let content = Box::new(self.read_group());
+                Some(MathNode::Phantom { content, width: cmd != "vphantom", height: cmd != "hphantom" })
+            }
+            "mathstrut" => {
+                let content = Box::new(MathNode::Symbol('('));
+                Some(MathNode::Phantom { content, width: false, height: true })
+            }
+            "smash" => {
+                let mut which = String::new();
+                if self.eat('[') {
+                    which = self.read_until(']');
+                    self.eat(']');
+                }
+                let content = Box::new(self.read_group());
+                let which = which.trim();
+                Some(MathNode::Smash { content, top: which != "b", bottom: which != "t" })
+            }
 
             // Function names
             "sin" | "cos" | "tan" | "cot" | "sec" | "csc"
             | "arcsin" | "arccos" | "arctan"
             | "sinh" | "cosh" | "tanh" | "coth"
-            | "log" | "ln" | "exp"
-            | "lim" | "limsup" | "liminf"
-            | "max" | "min" | "sup" | "inf"
-            | "det" | "gcd" | "lcm" | "dim" | "ker" | "deg"
-            | "arg" | "hom" | "Pr" | "mod" => {
-                Some(MathNode::Text(cmd.to_string()))
-            }
+            | "log" | "lg" | "ln" | "exp"
+            | "lcm" | "dim" | "ker" | "deg"
+            | "arg" | "hom" => named_op(cmd, Limits::Never),
+            "lim" | "max" | "min" | "sup" | "inf"
+            | "det" | "gcd" | "Pr" => named_op(cmd, Limits::Display),
+            "limsup" => named_op("lim sup", Limits::Display),
+            "liminf" => named_op("lim inf", Limits::Display),
+            "mod" => Some(MathNode::Text(cmd.to_string())),
+            "bmod" => Some(MathNode::Class(AtomClass::Bin, Box::new(MathNode::Text("mod".to_string())))),
+            "colon" => Some(MathNode::Class(AtomClass::Punct, Box::new(MathNode::Symbol(':')))),
+            "setminus" => sym('\u{2216}'),
 
             // \left ... \right
             "left" => {
+                let start = self.cmd_start(cmd);
                 let ld = self.read_delim_char();
                 let content = self.parse_expr_until(|_| false);
-                // We exited because we hit \right
-                self.advance(); // skip '\'
-                self.read_cmd(); // consume "right"
-                let rd = self.read_delim_char();
+                let mut middle = Vec::new();
+                while self.eat_cmd("middle") {
+                    let md = self.read_delim_char();
+                    middle.push((md, self.parse_expr_until(|_| false)));
+                }
+                let rd = if self.eat_cmd("right") {
+                    self.read_delim_char()
+                } else {
+                    self.error(ParseErrorKind::MissingRight, start, start + 5, "`\\left` without matching `\\right`");
+                    '\0'
+                };
                 Some(MathNode::Delimited {
                     left: ld,
                     right: rd,
                     content: Box::new(content),
+                    middle,
                 })
             }
-            "right" => None,
+            "right" | "middle" => None,
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "Bigl" | "biggl" | "Biggl" | "bigr" | "Bigr"
+            | "biggr" | "Biggr" | "bigm" | "Bigm" | "biggm" | "Biggm" => {
+                let size = match cmd.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => 1,
+                    "Big" => 2,
+                    "bigg" => 3,
+                    _ => 4,
+                };
+                let node = MathNode::BigDelim { delim: self.read_delim_char(), size };
+                let class = match cmd.chars().last() {
+                    Some('l') => AtomClass::Open,
+                    Some('r') => AtomClass::Close,
+                    Some('m') => AtomClass::Rel,
+                    _ => return Some(node),
+                };
+                Some(MathNode::Class(class, Box::new(node)))
+            }
 
             // Environments
             "begin" => {
+                let start = self.cmd_start(cmd);
                 let env = self.read_env_name();
-                self.parse_env(&env)
+                self.parse_env(&env, start)
             }
             "end" => {
                 self.read_env_name();
@@ -388,65 +1267,163 @@ impl Parser {
             "\\" => None,
 
             "not" => {
-                self.parse_single_atom()
+                let node = match self.parse_single_atom() {
+                    Some(MathNode::Symbol(ch)) => match negated(ch) {
+                        Some(neg) => return Some(MathNode::Symbol(neg)),
+                        None => MathNode::Symbol(ch),
+                    },
+                    Some(other) => other,
+                    None => MathNode::Row(vec![]),
+                };
+                Some(MathNode::Negated(Box::new(node)))
             }
 
-            _ => Some(MathNode::Text(format!("\\{}", cmd))),
+            // A style switch used as a script (`x^\scriptstyle a`) only has
+            // the next atom to apply to.
+            "displaystyle" | "textstyle" | "scriptstyle" | "scriptscriptstyle" => {
+                let content = self.read_group();
+                Some(MathNode::Style(style_cmd(cmd)?, Box::new(content)))
+            }
+
+            _ => {
+                let start = self.cmd_start(cmd);
+                self.error(ParseErrorKind::UnknownCommand, start, self.pos, format!("unknown command `\\{}`", cmd));
+                Some(MathNode::Text(format!("\\{}", cmd)))
+            }
         }
     }
 
+    /// The `{text}` argument of `\text`, `\fbox`, ...; `$..$` inside it is math.
+    fn read_text(&mut self) -> MathNode {
+        let t = self.read_raw_group();
+        if t.contains('$') { parse_tag(&t) } else { MathNode::Text(t) }
+    }
+
+    /// A color argument, `[model]{spec}`, for the command starting at `start`.
+    fn read_color(&mut self, start: usize) -> Option<Color> {
+        self.skip_ws();
+        let model = if self.eat('[') {
+            let model = self.read_until(']');
+            self.eat(']');
+            Some(model)
+        } else {
+            None
+        };
+        let spec = self.read_raw_group();
+        let color = color::parse_color(model.as_deref(), &spec);
+        if color.is_none() {
+            let message = match model {
+                Some(model) => format!("unknown color `[{}]{{{}}}`", model, spec),
+                None => format!("unknown color `{}`", spec),
+            };
+            self.error(ParseErrorKind::UnknownColor, start, self.pos, message);
+        }
+        color
+    }
+
+    /// Delimiter after `\left`, `\middle`, `\right` or `\big`; `'\0'` for `.`.
     fn read_delim_char(&mut self) -> char {
         self.skip_ws();
-        if let Some(ch) = self.peek() {
-            if ch == '\\' {
-                self.advance();
-                let cmd = self.read_cmd();
-                match cmd.as_str() {
-                    "{" | "lbrace" => '{',
-                    "}" | "rbrace" => '}',
-                    "|" | "lVert" | "rVert" => '\u{2016}',
-                    "langle" => '\u{27E8}',
-                    "rangle" => '\u{27E9}',
-                    "lceil" => '\u{2308}',
-                    "rceil" => '\u{2309}',
-                    "lfloor" => '\u{230A}',
-                    "rfloor" => '\u{230B}',
-                    "lvert" | "rvert" => '|',
-                    _ => '.',
-                }
-            } else {
-                self.advance();
-                if ch == '.' { '\0' } else { ch }
-            }
+        let start = self.pos;
+        let Some(ch) = self.advance() else {
+            self.error(ParseErrorKind::BadDelimiter, start, start, "missing delimiter");
+            return '\0';
+        };
+        let (delim, name) = if ch == '\\' {
+            let cmd = self.read_cmd();
+            (delimiter_cmd(&cmd), format!("\\{}", cmd))
         } else {
+            (delimiter_char(ch), ch.to_string())
+        };
+        delim.unwrap_or_else(|| {
+            let message = format!("`{}` is not a delimiter", name);
+            self.error(ParseErrorKind::BadDelimiter, start, self.pos, message);
             '\0'
-        }
+        })
     }
 
-    fn parse_env(&mut self, env: &str) -> Option<MathNode> {
+    /// Parse the body of environment `env`, whose `\\begin` is at `start`.
+    fn parse_env(&mut self, env: &str, start: usize) -> Option<MathNode> {
         match env {
-            "pmatrix" => self.parse_matrix(Some('('), Some(')')),
-            "bmatrix" => self.parse_matrix(Some('['), Some(']')),
-            "Bmatrix" => self.parse_matrix(Some('{'), Some('}')),
-            "vmatrix" => self.parse_matrix(Some('|'), Some('|')),
-            "Vmatrix" => self.parse_matrix(Some('\u{2016}'), Some('\u{2016}')),
-            "matrix" | "smallmatrix" => self.parse_matrix(None, None),
-            "cases" => {
-                let rows = self.parse_tabular();
-                Some(MathNode::Cases(rows))
+            _ if matrix_env(env).is_some() => {
+                let (left, right, small) = matrix_env(env)?;
+                self.parse_matrix(env, start, left, right, small)
+            }
+            "subarray" => {
+                let align = match self.read_raw_group().trim() {
+                    "l" => ColumnAlign::Left,
+                    "r" => ColumnAlign::Right,
+                    _ => ColumnAlign::Center,
+                };
+                let rows = self.parse_tabular(env, start);
+                Some(substack(rows, align))
+            }
+            "cases" | "dcases" | "rcases" | "drcases" => {
+                let rows = self.parse_tabular(env, start);
+                Some(MathNode::Cases {
+                    rows,
+                    display: env.starts_with('d'),
+                    right_brace: env.ends_with("rcases"),
+                })
             }
             "array" => {
+                // Vertical placement, as in `\begin{array}[t]{cc}`.
                 self.skip_ws();
-                if self.peek() == Some('{') {
+                if self.peek() == Some('[') {
+                    self.advance();
+                    self.read_until(']');
+                    self.eat(']');
+                }
+                let spec = self.parse_column_spec();
+                let (rows, hlines) = self.parse_rows(env, start);
+                Some(MathNode::Matrix {
+                    rows,
+                    left_delim: None,
+                    right_delim: None,
+                    spec: ArraySpec {
+                        hlines,
+                        stretch: self.array_stretch,
+                        column_sep: self.array_column_sep,
+                        ..spec
+                    },
+                })
+            }
+            _ if align_kind(env).is_some() => {
+                // Column count of `alignat`, placement of `aligned[t]`.
+                self.skip_ws();
+                if self.peek() == Some('[') {
+                    self.advance();
+                    self.read_until(']');
+                    self.eat(']');
+                }
+                if env.starts_with("alignat") || env == "alignedat" {
                     self.eat('{');
                     self.read_until('}');
                     self.eat('}');
                 }
-                self.parse_matrix(None, None)
+                let kind = align_kind(env)?;
+                let mut rows = self.parse_tabular(env, start);
+                if kind == AlignKind::Align {
+                    for cell in rows.iter_mut().flat_map(|r| r.iter_mut().skip(1).step_by(2)) {
+                        // Like amsmath, start each left-aligned cell with an empty
+                        // atom so a leading relation or operator keeps its spacing.
+                        let mut children = match std::mem::replace(cell, MathNode::Row(vec![])) {
+                            MathNode::Row(children) => children,
+                            other => vec![other],
+                        };
+                        children.insert(0, MathNode::Row(vec![]));
+                        *cell = MathNode::Row(children);
+                    }
+                }
+                Some(MathNode::Aligned { kind, rows })
             }
             _ => {
-                // aligned, gather, equation, etc. — parse as rows
-                let rows = self.parse_tabular();
+                if !is_display_env(env) {
+                    let message = format!("unknown environment `{}`", env);
+                    self.error(ParseErrorKind::UnknownEnvironment, start, self.pos, message);
+                }
+                // equation, displaymath, etc. — parse as rows
+                let rows = self.parse_tabular(env, start);
                 if rows.len() == 1 && rows[0].len() == 1 {
                     Some(rows.into_iter().next().unwrap().into_iter().next().unwrap())
                 } else {
@@ -454,25 +1431,100 @@ impl Parser {
                         rows,
                         left_delim: None,
                         right_delim: None,
+                        spec: ArraySpec::default(),
                     })
                 }
             }
         }
     }
 
-    fn parse_matrix(&mut self, left: Option<char>, right: Option<char>) -> Option<MathNode> {
-        let rows = self.parse_tabular();
+    fn parse_matrix(
+        &mut self,
+        env: &str,
+        start: usize,
+        left: Option<char>,
+        right: Option<char>,
+        small: bool,
+    ) -> Option<MathNode> {
+        // The starred mathtools forms take one alignment for every column:
+        // `\begin{pmatrix*}[r]`.
+        let mut align = ColumnAlign::Center;
+        if env.ends_with('*') {
+            self.skip_ws();
+            if self.peek() == Some('[') {
+                self.advance();
+                align = match self.read_until(']').trim() {
+                    "l" => ColumnAlign::Left,
+                    "r" => ColumnAlign::Right,
+                    _ => ColumnAlign::Center,
+                };
+                self.eat(']');
+            }
+        }
+        let (rows, hlines) = self.parse_rows(env, start);
+        let ncols = rows.iter().map(|row| row.iter().map(column_span).sum()).max().unwrap_or(0);
         Some(MathNode::Matrix {
             rows,
             left_delim: left,
             right_delim: right,
+            spec: ArraySpec {
+                columns: vec![align; ncols],
+                hlines,
+                small,
+                stretch: self.array_stretch,
+                column_sep: self.array_column_sep,
+                ..ArraySpec::default()
+            },
         })
     }
 
+    /// Column specification of an `array`: `{l|c@{,}r}`.
+    fn parse_column_spec(&mut self) -> ArraySpec {
+        let mut spec = ArraySpec { boundaries: vec![Boundary::default()], ..ArraySpec::default() };
+        if !self.eat('{') {
+            return spec;
+        }
+        loop {
+            self.skip_ws();
+            let Some(ch) = self.advance() else { break };
+            match ch {
+                '}' => break,
+                'l' | 'c' | 'r' | 'p' | 'm' | 'b' => {
+                    // Paragraph columns are set flush left; their width is ignored.
+                    if matches!(ch, 'p' | 'm' | 'b') && self.eat('{') {
+                        self.read_until('}');
+                        self.eat('}');
+                    }
+                    spec.columns.push(match ch {
+                        'c' => ColumnAlign::Center,
+                        'r' => ColumnAlign::Right,
+                        _ => ColumnAlign::Left,
+                    });
+                    spec.boundaries.push(Boundary::default());
+                }
+                '|' => spec.boundaries.last_mut().unwrap().rules += 1,
+                '@' => {
+                    let separator = self.read_group();
+                    spec.boundaries.last_mut().unwrap().separator = Some(separator);
+                }
+                _ => {}
+            }
+        }
+        spec
+    }
+
     /// Parse tabular content (& separates cells, \\ separates rows) until \end{...}
-    fn parse_tabular(&mut self) -> Vec<Vec<MathNode>> {
+    fn parse_tabular(&mut self, env: &str, start: usize) -> Vec<Vec<MathNode>> {
+        self.parse_rows(env, start).0
+    }
+
+    /// Rows of a tabular environment, plus the `\hline`/`\cline` rules above
+    /// each row and below the last. A `\\` right before `\end` does not
+    /// start another row.
+    fn parse_rows(&mut self, env: &str, start: usize) -> (Vec<Vec<MathNode>>, Vec<Vec<HRule>>) {
         let mut rows: Vec<Vec<MathNode>> = Vec::new();
         let mut row: Vec<MathNode> = Vec::new();
+        let mut hlines = vec![self.parse_hrules()];
 
         loop {
             let cell = self.parse_expr_until(|c| c == '&');
@@ -488,7 +1540,11 @@ impl Parser {
                     self.advance();
                     let cmd = self.read_cmd();
                     if cmd == "end" {
-                        self.read_env_name();
+                        let end = self.read_env_name();
+                        if end != env {
+                            let message = format!("`\\end{{{}}}` does not match `\\begin{{{}}}`", end, env);
+                            self.error(ParseErrorKind::MismatchedEnd, saved, self.pos, message);
+                        }
                         row.push(cell);
                         break;
                     } else if cmd == "\\" {
@@ -502,21 +1558,17 @@ impl Parser {
                             self.read_until(']');
                             self.eat(']');
                         }
-                    } else if cmd == "hline" || cmd == "cline" {
-                        if cmd == "cline" {
-                            self.eat('{');
-                            self.read_until('}');
-                            self.eat('}');
-                        }
-                        // Don't push cell, continue
+                        hlines.push(self.parse_hrules());
                     } else {
                         self.pos = saved;
                         row.push(cell);
+                        self.missing_end(env, start);
                         break;
                     }
                 }
                 _ => {
                     row.push(cell);
+                    self.missing_end(env, start);
                     break;
                 }
             }
@@ -524,7 +1576,79 @@ impl Parser {
         if !row.is_empty() {
             rows.push(row);
         }
-        rows
+        if rows.len() > 1 && matches!(rows.last().map(Vec::as_slice), Some([MathNode::Row(r)]) if r.is_empty()) {
+            rows.pop();
+        }
+        hlines.resize(rows.len() + 1, Vec::new());
+        (rows, hlines)
+    }
+
+    /// `\hline` and `\cline{i-j}` rules at the start of a row.
+    fn parse_hrules(&mut self) -> Vec<HRule> {
+        let mut rules = Vec::new();
+        loop {
+            if self.eat_cmd("hline") {
+                rules.push(HRule::Full);
+            } else if self.eat_cmd("cline") {
+                self.eat('{');
+                let range = self.read_until('}');
+                self.eat('}');
+                let mut bounds = range.split('-').map(|n| n.trim().parse::<usize>().unwrap_or(1).max(1));
+                let first = bounds.next().unwrap_or(1);
+                let last = bounds.next().unwrap_or(first).max(first);
+                rules.push(HRule::Columns(first - 1..last));
+            } else {
+                return rules;
+            }
+        }
+    }
+
+    fn missing_end(&mut self, env: &str, start: usize) {
+        let message = format!("`\\begin{{{}}}` without matching `\\end{{{}}}`", env, env);
+        self.error(ParseErrorKind::MissingEnd, start, start + 6, message);
+    }
+
+    /// Parse a whole equation. Tokens that end an expression early (a stray
+    /// `}`, `\\right` or `\\end`) are reported and skipped.
+    fn parse_all(&mut self) -> MathNode {
+        let mut parts = Vec::new();
+        loop {
+            parts.push(self.parse_expr_until(|_| false));
+            self.skip_ws();
+            let start = self.pos;
+            match self.advance() {
+                None => break,
+                Some('}') => self.error(ParseErrorKind::UnbalancedBrace, start, self.pos, "unmatched `}`"),
+                Some('&') => {
+                    self.error(ParseErrorKind::MisplacedAlignment, start, self.pos, "`&` outside an alignment")
+                }
+                Some('\\') => match self.read_cmd().as_str() {
+                    cmd @ ("right" | "middle") => {
+                        self.read_delim_char();
+                        let message = format!("`\\{}` without matching `\\left`", cmd);
+                        self.error(ParseErrorKind::UnmatchedRight, start, self.pos, message);
+                    }
+                    "end" => {
+                        let env = self.read_env_name();
+                        let message = format!("`\\end{{{}}}` without matching `\\begin`", env);
+                        self.error(ParseErrorKind::UnmatchedEnd, start, self.pos, message);
+                    }
+                    _ => self.error(
+                        ParseErrorKind::MisplacedAlignment,
+                        start,
+                        self.pos,
+                        "line break outside an alignment",
+                    ),
+                },
+                Some(_) => {}
+            }
+        }
+        parts.retain(|n| !matches!(n, MathNode::Row(r) if r.is_empty()));
+        match parts.len() {
+            0 => MathNode::Row(vec![]),
+            1 => parts.pop().unwrap(),
+            _ => MathNode::Row(parts),
+        }
     }
 }
 
@@ -532,6 +1656,102 @@ fn sym(c: char) -> Option<MathNode> {
     Some(MathNode::Symbol(c))
 }
 
+fn big_op(c: char, limits: Limits) -> Option<MathNode> {
+    Some(MathNode::BigOp { op: Box::new(MathNode::Symbol(c)), limits, sub: None, sup: None })
+}
+
+fn named_op(name: &str, limits: Limits) -> Option<MathNode> {
+    Some(MathNode::BigOp { op: Box::new(MathNode::Text(name.to_string())), limits, sub: None, sup: None })
+}
+
+fn style_cmd(name: &str) -> Option<MathStyle> {
+    match name {
+        "displaystyle" => Some(MathStyle::Display),
+        "textstyle" => Some(MathStyle::Text),
+        "scriptstyle" => Some(MathStyle::Script),
+        "scriptscriptstyle" => Some(MathStyle::ScriptScript),
+        _ => None,
+    }
+}
+
+/// Atom class of a single character symbol.
+fn char_class(ch: char) -> AtomClass {
+    match ch {
+        '+' | '\u{2212}' | '\u{00B1}' | '\u{2213}' | '\u{00D7}' | '\u{00F7}'
+        | '\u{22C5}' | '\u{2217}' | '\u{2218}' | '\u{2219}' | '\u{22C6}'
+        | '\u{222A}' | '\u{2229}' | '\u{2228}' | '\u{2227}' | '\u{2216}'
+        | '\u{2295}' | '\u{2296}' | '\u{2297}' | '\u{2298}' | '\u{2299}'
+        | '\u{228E}' | '\u{2293}' | '\u{2294}' | '\u{22C4}' | '\u{2020}' | '\u{2021}' => AtomClass::Bin,
+        '=' | '<' | '>' | ':'
+        | '\u{2190}'..='\u{21FF}' // arrows
+        | '\u{27F0}'..='\u{27FF}' // long arrows
+        | '\u{2208}'..='\u{220D}' // ∈ ∉ ∋ ...
+        | '\u{2223}'..='\u{2226}' // ∣ ∤ ∥ ∦
+        | '\u{2236}'..='\u{223D}' | '\u{2241}'..='\u{228B}' // ∼ ≃ ≅ ≈ ... ≤ ≥ ≪ ≫ ⊂ ⊃ ⊆ ⊇
+        | '\u{228F}'..='\u{2292}' | '\u{22A2}'..='\u{22A5}' | '\u{22A8}'..='\u{22AF}'
+        | '\u{22B2}'..='\u{22B5}' | '\u{22D0}'..='\u{22D1}' | '\u{22D8}'..='\u{22ED}'
+        | '\u{2A7D}'..='\u{2AB0}' | '\u{221D}' => AtomClass::Rel,
+        '(' | '[' | '{' | '\u{27E8}' | '\u{2308}' | '\u{230A}' | '\u{27E6}' | '\u{27EE}' | '\u{231C}'
+        | '\u{231E}' => AtomClass::Open,
+        ')' | ']' | '}' | '\u{27E9}' | '\u{2309}' | '\u{230B}' | '\u{27E7}' | '\u{27EF}' | '\u{231D}'
+        | '\u{231F}' | '!' | '?' => AtomClass::Close,
+        ',' | ';' => AtomClass::Punct,
+        '\u{2211}' | '\u{220F}' | '\u{2210}' | '\u{222B}'..='\u{2233}' | '\u{22C0}'..='\u{22C3}'
+        | '\u{2A00}'..='\u{2A06}' => AtomClass::Op,
+        _ => AtomClass::Ord,
+    }
+}
+
+/// Precomposed negation of a relation, for `\not`.
+fn negated(ch: char) -> Option<char> {
+    let neg = match ch {
+        '=' => '\u{2260}',
+        '<' => '\u{226E}',
+        '>' => '\u{226F}',
+        '|' | '\u{2223}' => '\u{2224}',
+        '\u{2208}' => '\u{2209}',
+        '\u{220B}' => '\u{220C}',
+        '\u{2203}' => '\u{2204}',
+        '\u{2225}' => '\u{2226}',
+        '\u{223C}' => '\u{2241}',
+        '\u{2243}' => '\u{2244}',
+        '\u{2245}' => '\u{2247}',
+        '\u{2248}' => '\u{2249}',
+        '\u{224D}' => '\u{226D}',
+        '\u{2261}' => '\u{2262}',
+        '\u{2264}' => '\u{2270}',
+        '\u{2265}' => '\u{2271}',
+        '\u{2272}' => '\u{2274}',
+        '\u{2273}' => '\u{2275}',
+        '\u{2276}' => '\u{2278}',
+        '\u{2277}' => '\u{2279}',
+        '\u{227A}' => '\u{2280}',
+        '\u{227B}' => '\u{2281}',
+        '\u{2282}' => '\u{2284}',
+        '\u{2283}' => '\u{2285}',
+        '\u{2286}' => '\u{2288}',
+        '\u{2287}' => '\u{2289}',
+        '\u{2291}' => '\u{22E2}',
+        '\u{2292}' => '\u{22E3}',
+        '\u{22A2}' => '\u{22AC}',
+        '\u{22A8}' => '\u{22AD}',
+        '\u{22A9}' => '\u{22AE}',
+        '\u{22AB}' => '\u{22AF}',
+        '\u{22B2}' => '\u{22EA}',
+        '\u{22B3}' => '\u{22EB}',
+        '\u{22B4}' => '\u{22EC}',
+        '\u{22B5}' => '\u{22ED}',
+        '\u{2190}' => '\u{219A}',
+        '\u{2192}' => '\u{219B}',
+        '\u{2194}' => '\u{21AE}',
+        '\u{21D0}' => '\u{21CD}',
+        '\u{21D2}' => '\u{21CF}',
+        '\u{21D4}' => '\u{21CE}',
+        _ => return None,
+    };
+    Some(neg)
+}
+
 fn is_math_char(ch: char) -> bool {
     ch.is_ascii_alphanumeric()
         || matches!(
@@ -541,23 +1761,324 @@ fn is_math_char(ch: char) -> bool {
         )
 }
 
-/// Parse a LaTeX math expression into an AST.
+/// Parse a LaTeX math expression into an AST, recovering from any errors.
 pub fn parse(input: &str) -> MathNode {
-    let input = strip_env_wrapper(input);
-    let mut p = Parser::new(&input);
-    p.parse_expr_until(|_| false)
+    parse_with_warnings(input).0
+}
+
+/// Parse a LaTeX math expression, failing on the first problem found.
+pub fn try_parse(input: &str) -> Result<MathNode, ParseError> {
+    let (node, mut errors) = parse_with_warnings(input);
+    if errors.is_empty() {
+        Ok(node)
+    } else {
+        Err(errors.swap_remove(0))
+    }
+}
+
+/// Parse a LaTeX math expression, recovering from errors. Returns the tree
+/// along with every problem found, in source order.
+pub fn parse_with_warnings(input: &str) -> (MathNode, Vec<ParseError>) {
+    let (inner, base) = strip_env_wrapper(input);
+    let mut p = Parser::new(inner, base);
+    let node = p.parse_all();
+    let mut errors = p.errors;
+    errors.sort_by_key(|e| e.span.start);
+    (node, errors)
 }
 
-fn strip_env_wrapper(input: &str) -> String {
-    let input = input.trim();
+/// A tag, or the argument of `\text`, as a node to draw: text, with `$..$`
+/// parts set as math. Each math part is a formula of its own, spaced like an
+/// ordinary atom.
+pub fn parse_tag(tag: &str) -> MathNode {
+    let parts: Vec<MathNode> = tag
+        .split('$')
+        .enumerate()
+        .filter(|(_, part)| !part.is_empty())
+        .map(|(i, part)| match i % 2 {
+            1 => MathNode::Class(AtomClass::Ord, Box::new(parse(part))),
+            _ => MathNode::Text(part.to_string()),
+        })
+        .collect();
+    MathNode::Row(parts)
+}
+
+/// The body of a `\\begin{equation}` (or similar) wrapper, and its byte offset.
+fn strip_env_wrapper(input: &str) -> (&str, usize) {
     let re = regex_lite::Regex::new(
         r"(?s)^\\begin\{(equation\*?|displaymath|math)\}(.*?)\\end\{(equation\*?|displaymath|math)\}$"
     )
     .unwrap();
-    if let Some(cap) = re.captures(input) {
+    let trimmed = input.trim();
+    let offset = input.len() - input.trim_start().len();
+    if let Some(cap) = re.captures(trimmed) {
         if cap.get(1).map(|m| m.as_str()) == cap.get(3).map(|m| m.as_str()) {
-            return cap[2].trim().to_string();
+            let body = cap.get(2).unwrap();
+            let lead = body.as_str().len() - body.as_str().trim_start().len();
+            return (body.as_str().trim(), offset + body.start() + lead);
         }
     }
-    input.to_string()
+    (trimmed, offset)
+}
+
+/// A switch that applies to the rest of its group: `\displaystyle`, `\color{red}`.
+enum Switch {
+    Style(MathStyle),
+    Color(Color),
+}
+
+/// A row of nodes, where each switch applies from its position to the end.
+fn with_styles(mut nodes: Vec<MathNode>, mut styles: Vec<(Switch, usize)>) -> MathNode {
+    while let Some((switch, start)) = styles.pop() {
+        let rest: Vec<_> = nodes.drain(start..).collect();
+        let content = Box::new(if rest.len() == 1 { rest.into_iter().next().unwrap() } else { MathNode::Row(rest) });
+        nodes.push(match switch {
+            Switch::Style(style) => MathNode::Style(style, content),
+            Switch::Color(color) => MathNode::Colored(color, content),
+        });
+    }
+
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        MathNode::Row(nodes)
+    }
+}
+
+fn fraction(num: MathNode, den: MathNode, rule: Option<f32>, left: char, right: char) -> MathNode {
+    MathNode::Frac { num: Box::new(num), den: Box::new(den), rule, left, right, num_align: ColumnAlign::Center }
+}
+
+/// Rule thickness and delimiters of a fraction written infix.
+type InfixFrac = (Option<f32>, char, char);
+
+/// `\over`, `\atop`, `\above`, `\choose`, `\brack` and `\brace`, which
+/// split their group into numerator and denominator.
+fn infix_frac(cmd: &str) -> Option<InfixFrac> {
+    let kind = match cmd {
+        "over" | "above" => (None, '\0', '\0'),
+        "atop" => (Some(0.0), '\0', '\0'),
+        "choose" => (Some(0.0), '(', ')'),
+        "brack" => (Some(0.0), '[', ']'),
+        "brace" => (Some(0.0), '{', '}'),
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// `\substack` and `subarray`: lines stacked in script style, as under a sum.
+fn substack(rows: Vec<Vec<MathNode>>, align: ColumnAlign) -> MathNode {
+    MathNode::Matrix {
+        rows,
+        left_delim: None,
+        right_delim: None,
+        spec: ArraySpec { columns: vec![align], small: true, ..ArraySpec::default() },
+    }
+}
+
+/// Delimiter written as a single character: `(`, `]`, `|`, `<`, `.`, ...
+fn delimiter_char(ch: char) -> Option<char> {
+    let delim = match ch {
+        '(' | ')' | '[' | ']' | '|' | '/' => ch,
+        '<' => '\u{27E8}',
+        '>' => '\u{27E9}',
+        '.' => '\0',
+        _ => return None,
+    };
+    Some(delim)
+}
+
+/// Delimiter written as a command: `\{`, `\langle`, `\Uparrow`, ...
+fn delimiter_cmd(cmd: &str) -> Option<char> {
+    let delim = match cmd {
+        "{" | "lbrace" => '{',
+        "}" | "rbrace" => '}',
+        "lbrack" => '[',
+        "rbrack" => ']',
+        "lparen" => '(',
+        "rparen" => ')',
+        "vert" | "lvert" | "rvert" => '|',
+        "|" | "Vert" | "lVert" | "rVert" => '\u{2016}',
+        "backslash" => '\\',
+        "langle" => '\u{27E8}',
+        "rangle" => '\u{27E9}',
+        "lceil" => '\u{2308}',
+        "rceil" => '\u{2309}',
+        "lfloor" => '\u{230A}',
+        "rfloor" => '\u{230B}',
+        "lgroup" => '\u{27EE}',
+        "rgroup" => '\u{27EF}',
+        "lmoustache" => '\u{23B0}',
+        "rmoustache" => '\u{23B1}',
+        "llbracket" => '\u{27E6}',
+        "rrbracket" => '\u{27E7}',
+        "ulcorner" => '\u{231C}',
+        "urcorner" => '\u{231D}',
+        "llcorner" => '\u{231E}',
+        "lrcorner" => '\u{231F}',
+        "uparrow" => '\u{2191}',
+        "downarrow" => '\u{2193}',
+        "updownarrow" => '\u{2195}',
+        "Uparrow" => '\u{21D1}',
+        "Downarrow" => '\u{21D3}',
+        "Updownarrow" => '\u{21D5}',
+        _ => return None,
+    };
+    Some(delim)
+}
+
+/// Number of matrix columns a cell takes up.
+pub(crate) fn column_span(cell: &MathNode) -> usize {
+    match cell {
+        MathNode::HDotsFor { columns, .. } => (*columns).max(1),
+        _ => 1,
+    }
+}
+
+/// A TeX length such as `2pt`, `0.5em` or `3mu`, in em (taking 1em = 10pt).
+fn length_em(text: &str) -> Option<f32> {
+    length(text).map(|length| match length {
+        Length::Relative(em) | Length::Absolute(em) => em,
+    })
+}
+
+/// A TeX length, keeping whether it follows the font size.
+fn length(text: &str) -> Option<Length> {
+    let text = text.trim();
+    let split = text.find(|c: char| c.is_ascii_alphabetic())?;
+    let value: f32 = text[..split].trim().parse().ok()?;
+    let relative = |unit: f32| Some(Length::Relative(value * unit));
+    let absolute = |unit: f32| Some(Length::Absolute(value * unit));
+    match text[split..].trim() {
+        "em" => relative(1.0),
+        "ex" => relative(0.43),
+        "mu" => relative(1.0 / 18.0),
+        "pt" => absolute(0.1),
+        "bp" => absolute(0.1 * 72.27 / 72.0),
+        "pc" => absolute(1.2),
+        "mm" => absolute(0.1 * 72.27 / 25.4),
+        "cm" => absolute(72.27 / 25.4),
+        "in" => absolute(7.227),
+        _ => None,
+    }
+}
+
+/// The arrow an extensible arrow command (amsmath's and mathtools') stretches.
+fn xarrow_char(cmd: &str) -> Option<char> {
+    let arrow = match cmd {
+        "xrightarrow" => '\u{2192}',
+        "xleftarrow" => '\u{2190}',
+        "xRightarrow" => '\u{21D2}',
+        "xLeftarrow" => '\u{21D0}',
+        "xleftrightarrow" => '\u{2194}',
+        "xLeftrightarrow" => '\u{21D4}',
+        "xmapsto" => '\u{21A6}',
+        "xhookleftarrow" => '\u{21A9}',
+        "xhookrightarrow" => '\u{21AA}',
+        "xrightharpoonup" => '\u{21C0}',
+        "xrightharpoondown" => '\u{21C1}',
+        "xleftharpoonup" => '\u{21BC}',
+        "xleftharpoondown" => '\u{21BD}',
+        "xrightleftharpoons" => '\u{21CC}',
+        "xleftrightharpoons" => '\u{21CB}',
+        "xlongequal" => '=',
+        _ => return None,
+    };
+    Some(arrow)
+}
+
+/// Delimiters and size of a matrix environment: `pmatrix`, `bsmallmatrix`,
+/// `Vmatrix*`, ...
+fn matrix_env(env: &str) -> Option<(Option<char>, Option<char>, bool)> {
+    let base = env.trim_end_matches('*').strip_suffix("matrix")?;
+    let small = base.ends_with("small");
+    let delims = match base.trim_end_matches("small") {
+        "" => (None, None),
+        "p" => (Some('('), Some(')')),
+        "b" => (Some('['), Some(']')),
+        "B" => (Some('{'), Some('}')),
+        "v" => (Some('|'), Some('|')),
+        "V" => (Some('\u{2016}'), Some('\u{2016}')),
+        _ => return None,
+    };
+    Some((delims.0, delims.1, small))
+}
+
+/// Alignment environments and how they line up their rows.
+fn align_kind(env: &str) -> Option<AlignKind> {
+    let kind = match env.trim_end_matches('*') {
+        "align" | "aligned" | "alignat" | "alignedat" | "split" | "flalign" => AlignKind::Align,
+        "gather" | "gathered" => AlignKind::Gather,
+        "multline" => AlignKind::Multline,
+        "eqnarray" => AlignKind::EqnArray,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Display environments without alignment of their own, whose content is
+/// laid out as plain rows. The alignment family goes through `align_kind`.
+fn is_display_env(env: &str) -> bool {
+    matches!(env.trim_end_matches('*'), "equation" | "displaymath" | "math")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kind and byte span of every error found in `input`.
+    fn errors(input: &str) -> Vec<(ParseErrorKind, Range<usize>)> {
+        parse_with_warnings(input).1.into_iter().map(|e| (e.kind, e.span)).collect()
+    }
+
+    #[test]
+    fn well_formed_input_has_no_errors() {
+        assert_eq!(errors(r"\frac{a}{b} + \left( x^2_i \right)"), []);
+        assert!(try_parse(r"\sqrt{x}").is_ok());
+    }
+
+    #[test]
+    fn stray_closing_brace() {
+        assert_eq!(errors("a}b"), [(ParseErrorKind::UnbalancedBrace, 1..2)]);
+        // Spans are byte offsets, so they stay valid after multi-byte text.
+        assert_eq!(errors("α}"), [(ParseErrorKind::UnbalancedBrace, 2..3)]);
+        assert_eq!(errors(r"\sqrt{x"), [(ParseErrorKind::UnbalancedBrace, 5..6)]);
+    }
+
+    #[test]
+    fn alignment_outside_an_environment() {
+        assert_eq!(errors("a & b"), [(ParseErrorKind::MisplacedAlignment, 2..3)]);
+        assert_eq!(errors(r"a \\ b"), [(ParseErrorKind::MisplacedAlignment, 2..4)]);
+    }
+
+    #[test]
+    fn unmatched_delimiters() {
+        assert_eq!(errors(r"a \right) b"), [(ParseErrorKind::UnmatchedRight, 2..9)]);
+        assert_eq!(errors(r"\left( a"), [(ParseErrorKind::MissingRight, 0..5)]);
+    }
+
+    #[test]
+    fn missing_argument_points_at_the_end() {
+        assert_eq!(errors(r"\frac{a}"), [(ParseErrorKind::MissingArgument, 8..8)]);
+        assert_eq!(errors("x^"), [(ParseErrorKind::MissingArgument, 2..2)]);
+    }
+
+    #[test]
+    fn double_scripts() {
+        assert_eq!(errors("x^a^b"), [(ParseErrorKind::DoubleScript, 3..4)]);
+        assert_eq!(errors("x_a_b"), [(ParseErrorKind::DoubleScript, 3..4)]);
+    }
+
+    #[test]
+    fn unknown_commands_and_environments() {
+        assert_eq!(errors(r"\foo + 1"), [(ParseErrorKind::UnknownCommand, 0..4)]);
+        assert_eq!(errors(r"\begin{matrix} a"), [(ParseErrorKind::MissingEnd, 0..6)]);
+        assert_eq!(errors(r"\end{matrix}"), [(ParseErrorKind::UnmatchedEnd, 0..12)]);
+    }
+
+    #[test]
+    fn try_parse_reports_the_first_error() {
+        let err = try_parse(r"x^a^b + \foo").unwrap_err();
+        assert_eq!((err.kind, err.span), (ParseErrorKind::DoubleScript, 3..4));
+    }
 }
@@ -1,8 +1,31 @@
 use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont};
 use std::io::BufWriter;
 
-use crate::parser::MathNode;
+use crate::alphabet;
+use crate::mathfont::{MathConstants, MathFont, Stretched};
+use crate::parser::{
+    column_span, AlignKind, ArraySpec, AtomClass, Boundary, CancelKind, Color, ColumnAlign, HRule, Length, Limits, MathNode, MathStyle, MathVariant,
+};
+use crate::svg;
+
+/// Output image format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Svg,
+}
+
+impl OutputFormat {
+    /// File extension (without the dot) for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+        }
+    }
+}
 
+/// Foreground/background color scheme of the rendered image.
 #[derive(Debug, Clone)]
 pub enum Theme {
     Dark,
@@ -54,15 +77,38 @@ impl ImageBuf {
         self.data[idx + 3] = 255;
     }
 
-    fn save_png(&self, path: &std::path::Path) -> anyhow::Result<()> {
-        let file = std::fs::File::create(path)?;
-        let w = BufWriter::new(file);
-        let mut encoder = png::Encoder::new(w, self.width, self.height);
-        encoder.set_color(png::ColorType::Rgba);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header()?;
-        writer.write_image_data(&self.data)?;
-        Ok(())
+    /// Fill the rectangle with its top-left corner at (x, y), snapped to whole pixels.
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [u8; 4]) {
+        let (xs, xe) = (x.round().max(0.0) as u32, (x + width).round().max(0.0) as u32);
+        let (ys, ye) = (y.round().max(0.0) as u32, (y + height).round().max(0.0) as u32);
+        for py in ys..ye {
+            for px in xs..xe {
+                self.put_pixel(px, py, color, 255);
+            }
+        }
+    }
+
+    /// Outline the rectangle with lines `thickness` wide (at least a pixel)
+    /// running just inside its bounds.
+    fn frame_rect(&mut self, x: f32, y: f32, width: f32, height: f32, thickness: f32, color: [u8; 4]) {
+        let t = thickness.max(1.0);
+        self.fill_rect(x, y, width, t, color);
+        self.fill_rect(x, y + height - t, width, t, color);
+        self.fill_rect(x, y + t, t, height - 2.0 * t, color);
+        self.fill_rect(x + width - t, y + t, t, height - 2.0 * t, color);
+    }
+
+    fn encode_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        {
+            let w = BufWriter::new(&mut out);
+            let mut encoder = png::Encoder::new(w, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.data)?;
+        }
+        Ok(out)
     }
 }
 
@@ -80,18 +126,159 @@ impl Dims {
     }
 }
 
-/// Positioned element ready to draw.
-enum DrawCmd {
-    Glyph { x: f32, y: f32, ch: char, size: f32 },
-    HLine { x: f32, y: f32, width: f32, thickness: f32 },
-    Line { x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32 },
-    Text { x: f32, y: f32, text: String, size: f32 },
+/// TeX math style a node is laid out in, and the pixel size it implies.
+///
+/// Cramped styles (denominators, subscripts, radicands) keep superscripts low.
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    level: MathStyle,
+    cramped: bool,
+    /// Text-style font size in pixels; script levels scale down from it.
+    base: f32,
+    size: f32,
+    /// Math alphabet letters and digits are drawn in, if any.
+    variant: Option<MathVariant>,
+}
+
+impl Style {
+    fn new(level: MathStyle, base: f32, c: &MathConstants) -> Self {
+        Style { level, cramped: false, base, size: base, variant: None }.at(level, c)
+    }
+
+    fn at(self, level: MathStyle, c: &MathConstants) -> Self {
+        let size = match level {
+            MathStyle::Display | MathStyle::Text => self.base,
+            MathStyle::Script => self.base * c.script_percent_scale_down,
+            MathStyle::ScriptScript => self.base * c.script_script_percent_scale_down,
+        };
+        Style { level, size, ..self }
+    }
+
+    fn is_display(self) -> bool {
+        self.level == MathStyle::Display
+    }
+
+    fn cramp(self) -> Self {
+        Style { cramped: true, ..self }
+    }
+
+    /// An explicit style switch (`\displaystyle`), which is never cramped.
+    fn switch(self, level: MathStyle, c: &MathConstants) -> Self {
+        Style { cramped: false, ..self.at(level, c) }
+    }
+
+    fn sup(self, c: &MathConstants) -> Self {
+        let level = match self.level {
+            MathStyle::Display | MathStyle::Text => MathStyle::Script,
+            _ => MathStyle::ScriptScript,
+        };
+        self.at(level, c)
+    }
+
+    fn sub(self, c: &MathConstants) -> Self {
+        self.sup(c).cramp()
+    }
+
+    fn num(self, c: &MathConstants) -> Self {
+        let level = match self.level {
+            MathStyle::Display => MathStyle::Text,
+            MathStyle::Text => MathStyle::Script,
+            _ => MathStyle::ScriptScript,
+        };
+        self.at(level, c)
+    }
+
+    fn den(self, c: &MathConstants) -> Self {
+        self.num(c).cramp()
+    }
+
+    /// Enter a math alphabet; alphabets nested in a bold one become bold too.
+    fn styled(self, variant: MathVariant) -> Self {
+        let variant = match self.variant {
+            Some(MathVariant::Bold | MathVariant::BoldItalic) => alphabet::embolden(variant),
+            _ => variant,
+        };
+        Style { variant: Some(variant), ..self }
+    }
+
+    /// Matrix and array cells are typeset in text style.
+    fn cells(self, c: &MathConstants) -> Self {
+        self.switch(MathStyle::Text, c)
+    }
+}
+
+/// Positioned element ready to draw, in its own color or (`None`) the
+/// theme's foreground.
+pub(crate) enum DrawCmd {
+    Glyph { x: f32, y: f32, id: GlyphId, size: f32, color: Option<[u8; 4]> },
+    HLine { x: f32, y: f32, width: f32, thickness: f32, color: Option<[u8; 4]> },
+    /// Straight stroke between two points (`array` rules, `\cancel` strokes).
+    Line { x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, color: Option<[u8; 4]> },
+    Text { x: f32, y: f32, text: String, size: f32, color: Option<[u8; 4]> },
+    /// Filled rectangle with its top-left corner at (x, y) (a `\colorbox` background).
+    Rect { x: f32, y: f32, width: f32, height: f32, color: Option<[u8; 4]> },
+    /// Rectangle outline drawn inside the given bounds (a `\boxed` frame).
+    Frame { x: f32, y: f32, width: f32, height: f32, thickness: f32, color: Option<[u8; 4]> },
 }
 
+impl DrawCmd {
+    pub(crate) fn color(&self) -> Option<[u8; 4]> {
+        match *self {
+            DrawCmd::Glyph { color, .. }
+            | DrawCmd::HLine { color, .. }
+            | DrawCmd::Line { color, .. }
+            | DrawCmd::Text { color, .. }
+            | DrawCmd::Rect { color, .. }
+            | DrawCmd::Frame { color, .. } => color,
+        }
+    }
+
+    fn color_mut(&mut self) -> &mut Option<[u8; 4]> {
+        match self {
+            DrawCmd::Glyph { color, .. }
+            | DrawCmd::HLine { color, .. }
+            | DrawCmd::Line { color, .. }
+            | DrawCmd::Text { color, .. }
+            | DrawCmd::Rect { color, .. }
+            | DrawCmd::Frame { color, .. } => color,
+        }
+    }
+}
+
+/// Rendering options shared by the CLI and the in-memory API.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub theme: Theme,
+    /// Font size in points.
+    pub font_size: f32,
+    /// Render scale factor.
+    pub scale: f32,
+    /// Typeset in display style (`$$...$$`, environments) rather than inline.
+    pub display: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            theme: Theme::Dark,
+            font_size: 24.0,
+            scale: 3.0,
+            display: true,
+        }
+    }
+}
+
+/// Lays out a [`MathNode`] tree with the embedded STIX Two Math font.
 pub struct Renderer {
     font_data: &'static [u8],
 }
 
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Renderer {
     pub fn new() -> Self {
         Renderer {
@@ -99,529 +286,1895 @@ impl Renderer {
         }
     }
 
+    /// Render an equation and write it to `output` as a PNG file.
     pub fn render_equation(
         &self,
         node: &MathNode,
-        theme: &Theme,
-        font_size: f32,
-        scale: f32,
+        options: &Options,
         output: &std::path::Path,
     ) -> anyhow::Result<()> {
-        let font = FontRef::try_from_slice(self.font_data)
-            .map_err(|e| anyhow::anyhow!("Font load error: {}", e))?;
-
-        let px_size = font_size * scale;
-        let sf = font.as_scaled(PxScale::from(px_size));
-        let padding = (16.0 * scale) as u32;
-
-        let dims = measure(&font, &sf, node, px_size);
-
-        let img_w = (dims.width as u32 + padding * 2).max(1);
-        let img_h = (dims.height() as u32 + padding * 2).max(1);
+        let png = self.render_png(node, options)?;
+        std::fs::write(output, png)?;
+        Ok(())
+    }
 
-        let mut img = ImageBuf::new(img_w, img_h, theme.bg());
+    /// Render an equation to PNG-encoded bytes without touching the filesystem.
+    pub fn render_png(&self, node: &MathNode, options: &Options) -> anyhow::Result<Vec<u8>> {
+        let font = self.font()?;
+        let scene = compose(&font, node, options);
 
-        let mut cmds = Vec::new();
-        let origin_x = padding as f32;
-        let origin_y = padding as f32 + dims.ascent;
-        layout(&font, &sf, node, px_size, origin_x, origin_y, &mut cmds);
+        let mut img = ImageBuf::new(scene.width, scene.height, options.theme.bg());
 
-        let fg = theme.fg();
-        for cmd in &cmds {
-            match cmd {
-                DrawCmd::Glyph { x, y, ch, size } => {
-                    draw_char(&font, &mut img, *ch, *x, *y, *size, fg);
+        let fg = options.theme.fg();
+        for cmd in &scene.cmds {
+            match *cmd {
+                DrawCmd::Glyph { x, y, id, size, color } => {
+                    draw_glyph(&font.font, &mut img, id, x, y, size, color.unwrap_or(fg));
+                }
+                DrawCmd::HLine { x, y, width, thickness, color } => {
+                    draw_hline(&mut img, x, y, width, thickness, color.unwrap_or(fg));
+                }
+                DrawCmd::Line { x1, y1, x2, y2, thickness, color } => {
+                    draw_line(&mut img, x1, y1, x2, y2, thickness, color.unwrap_or(fg));
                 }
-                DrawCmd::HLine { x, y, width, thickness } => {
-                    draw_hline(&mut img, *x, *y, *width, *thickness, fg);
+                DrawCmd::Text { x, y, ref text, size, color } => {
+                    draw_text_str(&font.font, &mut img, text, x, y, size, color.unwrap_or(fg));
                 }
-                DrawCmd::Line { x1, y1, x2, y2, thickness } => {
-                    draw_line(&mut img, *x1, *y1, *x2, *y2, *thickness, fg);
+                DrawCmd::Rect { x, y, width, height, color } => {
+                    img.fill_rect(x, y, width, height, color.unwrap_or(fg));
                 }
-                DrawCmd::Text { x, y, text, size } => {
-                    draw_text_str(&font, &mut img, text, *x, *y, *size, fg);
+                DrawCmd::Frame { x, y, width, height, thickness, color } => {
+                    img.frame_rect(x, y, width, height, thickness, color.unwrap_or(fg));
                 }
             }
         }
 
-        img.save_png(output)
+        img.encode_png()
     }
+
+    /// Render an equation as a standalone SVG document with glyphs as outline paths.
+    pub fn render_svg(&self, node: &MathNode, options: &Options) -> anyhow::Result<String> {
+        let font = self.font()?;
+        let scene = compose(&font, node, options);
+        Ok(svg::write_svg(&font.font, &scene, &options.theme, options.scale))
+    }
+
+    fn font(&self) -> anyhow::Result<MathFont<'static>> {
+        MathFont::new(self.font_data)
+    }
+}
+
+/// A laid-out equation: canvas size in pixels plus the draw commands on it.
+pub(crate) struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub cmds: Vec<DrawCmd>,
+}
+
+/// Measure and lay out `node`; shared by every output format.
+fn compose(font: &MathFont, node: &MathNode, options: &Options) -> Scene {
+    let px_size = options.font_size * options.scale;
+    let padding = (16.0 * options.scale) as u32;
+
+    let level = if options.display { MathStyle::Display } else { MathStyle::Text };
+    let st = Style::new(level, px_size, &font.constants);
+    let dims = measure(font, node, st);
+
+    let width = (dims.width as u32 + padding * 2).max(1);
+    let height = (dims.height() as u32 + padding * 2).max(1);
+
+    let mut cmds = Vec::new();
+    let origin_x = padding as f32;
+    let origin_y = padding as f32 + dims.ascent;
+    layout(font, node, st, origin_x, origin_y, &mut cmds);
+
+    Scene { width, height, cmds }
 }
 
 // ─── Helpers ────────────────────────────────────────────────────────────────
 
-/// Returns true if this node should have extra spacing around it (binary ops, relations).
-fn is_spaced_node(node: &MathNode) -> bool {
-    match node {
-        MathNode::Symbol(ch) => is_bin_or_rel(*ch),
-        _ => false,
+/// Space between matrix columns (2 × \arraycolsep), in units of the font size.
+const MATRIX_COL_GAP: f32 = 1.0;
+/// Minimum row height above/below the baseline (the array strut), in units of the font size.
+const MATRIX_STRUT_ASCENT: f32 = 0.84;
+const MATRIX_STRUT_DESCENT: f32 = 0.36;
+/// Space between `smallmatrix` columns (\thickspace), in units of the font size.
+const SMALLMATRIX_COL_GAP: f32 = 5.0 / 18.0;
+/// \arraystretch inside `cases`.
+const CASES_STRETCH: f32 = 1.2;
+/// Thickness of `array` rules (\arrayrulewidth), in units of the font size.
+const ARRAY_RULE_WIDTH: f32 = 0.04;
+/// Space between the lines of `||` and `\hline\hline` (\doublerulesep), in units of the font size.
+const DOUBLE_RULE_SEP: f32 = 0.2;
+/// Minimum space between the column pairs of an alignment (\minalignsep), in units of the font size.
+const ALIGN_COLUMN_GAP: f32 = 1.0;
+/// Extra space between the rows of an alignment (\jot), in units of the font size.
+const ALIGN_JOT: f32 = 0.3;
+/// Indent of the first and last rows of `multline` (\multlinegap), in units of the font size.
+const MULTLINE_GAP: f32 = 1.0;
+/// Minimum space between an equation and its tags (\qquad), in units of the font size.
+const TAG_GAP: f32 = 2.0;
+/// Padding between a `\boxed` or `\colorbox` and its content (\fboxsep), in units of the font size.
+const FBOX_SEP: f32 = 0.3;
+/// Thickness of a `\boxed` frame (\fboxrule), in units of the font size.
+const FBOX_RULE: f32 = 0.04;
+/// Space on either side of the labels of an extensible arrow, in units of the font size.
+const XARROW_PAD: f32 = 0.4;
+/// Length and rise of the barbs of an arrow drawn with lines, in units of the font size.
+const LINE_ARROW_HEAD: f32 = 0.3;
+const LINE_ARROW_BARB: f32 = 0.15;
+/// Thickness of `\cancel` strokes (\fboxrule in the cancel package), in units of the font size.
+const CANCEL_RULE: f32 = 0.04;
+/// How far a `\cancelto` arrow runs past the content, and the length of its
+/// head's barbs, in units of the font size.
+const CANCELTO_OVERSHOOT: f32 = 0.3;
+const CANCELTO_HEAD: f32 = 0.2;
+/// Least space between a wide accent's ink and the content under it, in em.
+const WIDE_ACCENT_GAP: f32 = 0.1;
+/// \nulldelimiterspace, added on both sides of a fraction, in units of the font size.
+const NULL_DELIMITER_SPACE: f32 = 0.12;
+/// TeX's \delimiterfactor (901/1000) and \delimitershortfall (5pt at 10pt).
+const DELIMITER_FACTOR: f32 = 0.901;
+const DELIMITER_SHORTFALL: f32 = 0.5;
+/// Height covered by `\big`, `\Big`, `\bigg` and `\Bigg` (amsmath's 1.2\ht\strutbox
+/// times 1, 1.5, 2 and 2.5, with \ht\strutbox at 0.85em), in units of the font size.
+const BIG_DELIM_HEIGHTS: [f32; 4] = [1.02, 1.53, 2.04, 2.55];
+/// Height of the delimiters around a generalized fraction such as `\binom`
+/// (TeX's \delim1 and \delim2), in units of the font size.
+const FRAC_DELIM_DISPLAY: f32 = 2.39;
+const FRAC_DELIM_TEXT: f32 = 1.01;
+
+/// Inter-atom space from TeX's spacing table, in mu (1/18 em): 3 is a thin,
+/// 4 a medium and 5 a thick space. Medium and thick spaces, and the thin
+/// spaces involving Punct and Inner, vanish in script styles.
+fn atom_space(left: AtomClass, right: AtomClass, st: Style) -> f32 {
+    use AtomClass::*;
+    // (mu, kept in script styles)
+    let (mu, in_scripts) = match (left, right) {
+        (Ord, Op) | (Op, Ord) | (Op, Op) | (Close, Op) | (Inner, Op) => (3.0, true),
+        (Ord, Inner) | (Op, Inner) | (Close, Inner) | (Inner, Ord) | (Inner, Open)
+        | (Inner, Punct) | (Inner, Inner) | (Punct, _) => (3.0, false),
+        (Ord | Close | Inner, Bin) | (Bin, Ord | Op | Open | Inner) => (4.0, false),
+        (Ord | Op | Close | Inner, Rel) | (Rel, Ord | Op | Open | Inner) => (5.0, false),
+        _ => (0.0, true),
+    };
+    if in_scripts || st.level <= MathStyle::Text {
+        mu / 18.0 * st.size
+    } else {
+        0.0
     }
 }
 
-fn is_bin_or_rel(ch: char) -> bool {
-    matches!(ch,
-        '=' | '<' | '>' | '+' | '-'
-        | '\u{2264}' | '\u{2265}' | '\u{2260}' | '\u{2248}' | '\u{2261}' // ≤ ≥ ≠ ≈ ≡
-        | '\u{223C}' | '\u{2243}' | '\u{2245}' | '\u{221D}'  // ~ ≃ ≅ ∝
-        | '\u{2282}' | '\u{2283}' | '\u{2286}' | '\u{2287}'  // ⊂ ⊃ ⊆ ⊇
-        | '\u{2208}' | '\u{2209}' | '\u{220B}'  // ∈ ∉ ∋
-        | '\u{222A}' | '\u{2229}'  // ∪ ∩
-        | '\u{2228}' | '\u{2227}'  // ∨ ∧
-        | '\u{00D7}' | '\u{00F7}'  // × ÷
-        | '\u{00B1}' | '\u{2213}'  // ± ∓
-        | '\u{2192}' | '\u{2190}' | '\u{2194}'  // → ← ↔
-        | '\u{21D2}' | '\u{21D0}' | '\u{21D4}'  // ⇒ ⇐ ⇔
-        | '\u{21A6}'  // ↦
-        | '\u{226A}' | '\u{226B}'  // ≪ ≫
-    )
+/// Space to put before each child of a row. A binary operator with nothing
+/// to combine on one side (`-x`, `(-1)`, `= -2`) is set as an ordinary atom.
+fn row_spacing(children: &[MathNode], st: Style) -> Vec<f32> {
+    let mut classes: Vec<Option<AtomClass>> = children.iter().map(MathNode::atom_class).collect();
+    let mut prev: Option<usize> = None;
+    for i in 0..classes.len() {
+        let Some(class) = classes[i] else { continue };
+        let before = prev.and_then(|p| classes[p]);
+        if class == AtomClass::Bin
+            && matches!(before, None | Some(AtomClass::Bin | AtomClass::Op | AtomClass::Rel | AtomClass::Open | AtomClass::Punct))
+        {
+            classes[i] = Some(AtomClass::Ord);
+        }
+        if matches!(class, AtomClass::Rel | AtomClass::Close | AtomClass::Punct) && before == Some(AtomClass::Bin) {
+            classes[prev.unwrap()] = Some(AtomClass::Ord);
+        }
+        prev = Some(i);
+    }
+    if let Some(p) = prev {
+        if classes[p] == Some(AtomClass::Bin) {
+            classes[p] = Some(AtomClass::Ord);
+        }
+    }
+
+    let mut spaces = vec![0.0; children.len()];
+    let mut prev: Option<AtomClass> = None;
+    for (i, class) in classes.iter().enumerate() {
+        if let Some(class) = *class {
+            if let Some(p) = prev {
+                spaces[i] = atom_space(p, class, st);
+            }
+            prev = Some(class);
+        }
+    }
+    spaces
 }
 
 // ─── Measurement ────────────────────────────────────────────────────────────
 
-fn measure(font: &FontRef, sf: &ab_glyph::PxScaleFont<&FontRef>, node: &MathNode, size: f32) -> Dims {
+fn measure(font: &MathFont, node: &MathNode, st: Style) -> Dims {
+    let c = &font.constants;
+    let size = st.size;
     match node {
-        MathNode::Symbol(ch) => measure_char(sf, *ch, size),
-        MathNode::Text(t) => measure_text(sf, t, size),
+        MathNode::Symbol(ch) => measure_char(font, styled_char(font, st, *ch), size),
+        MathNode::Text(t) => measure_text(font, t, size),
         MathNode::Space(em) => Dims { width: em * size, ascent: 0.0, descent: 0.0 },
+        MathNode::Kern(length) => {
+            let width = match *length {
+                Length::Relative(em) => em * size,
+                Length::Absolute(em) => em * st.base,
+            };
+            Dims { width, ascent: 0.0, descent: 0.0 }
+        }
+        MathNode::Phantom { content, width, height } => {
+            let d = measure(font, content, st);
+            Dims {
+                width: if *width { d.width } else { 0.0 },
+                ascent: if *height { d.ascent } else { 0.0 },
+                descent: if *height { d.descent } else { 0.0 },
+            }
+        }
+        MathNode::Smash { content, top, bottom } => {
+            let d = measure(font, content, st);
+            Dims {
+                width: d.width,
+                ascent: if *top { 0.0 } else { d.ascent },
+                descent: if *bottom { 0.0 } else { d.descent },
+            }
+        }
+        // Fills the columns it spans; only a matrix gives it any width.
+        MathNode::HDotsFor { .. } => Dims { width: 0.0, ascent: 0.0, descent: 0.0 },
 
         MathNode::Row(children) => {
-            let gap = size * 0.12;
             let mut w = 0.0f32;
             let mut asc = 0.0f32;
             let mut desc = 0.0f32;
-            for (i, child) in children.iter().enumerate() {
-                let d = measure(font, sf, child, size);
-                if i > 0 {
-                    w += if is_spaced_node(child) || (i > 0 && is_spaced_node(&children[i - 1])) {
-                        size * 0.2
-                    } else {
-                        gap
-                    };
-                }
-                w += d.width;
+            for (child, space) in children.iter().zip(row_spacing(children, st)) {
+                let d = measure(font, child, st);
+                w += space + d.width;
                 asc = asc.max(d.ascent);
                 desc = desc.max(d.descent);
             }
             Dims { width: w, ascent: asc, descent: desc }
         }
 
-        MathNode::Frac(num, den) => {
-            let ns = size * 0.8;
-            let nsf = font.as_scaled(PxScale::from(ns));
-            let n = measure(font, &nsf, num, ns);
-            let d = measure(font, &nsf, den, ns);
-            let rule = size * 0.05;
-            let gap = size * 0.25;
-            let axis_offset = size * 0.22;
-            let w = n.width.max(d.width) + size * 0.3;
-            Dims {
-                width: w,
-                ascent: n.height() + gap + rule / 2.0 + axis_offset,
-                descent: (d.height() + gap + rule / 2.0 - axis_offset).max(d.height() * 0.5),
-            }
-        }
+        MathNode::Frac { num, den, rule, left, right, .. } => place_frac(font, num, den, *rule, *left, *right, st).dims,
 
         MathNode::Sup(base, exp) => {
-            let b = measure(font, sf, base, size);
-            let es = size * 0.65;
-            let esf = font.as_scaled(PxScale::from(es));
-            let e = measure(font, &esf, exp, es);
-            let shift = b.ascent * 0.5;
+            let b = measure(font, base, st);
+            let e = measure(font, exp, st.sup(c));
+            let shift = sup_shift(font, is_char_base(base), b, e, st);
             Dims {
-                width: b.width + e.width + size * 0.03,
+                width: b.width + italic_correction(font, base, st) + e.width + c.space_after_script * size,
                 ascent: b.ascent.max(shift + e.ascent),
-                descent: b.descent,
+                descent: b.descent.max(e.descent - shift),
             }
         }
 
         MathNode::Sub(base, idx) => {
-            let b = measure(font, sf, base, size);
-            let is = size * 0.65;
-            let isf = font.as_scaled(PxScale::from(is));
-            let i = measure(font, &isf, idx, is);
-            let shift = b.descent + b.ascent * 0.2;
+            let b = measure(font, base, st);
+            let i = measure(font, idx, st.sub(c));
+            let shift = sub_shift(font, is_char_base(base), b, i, st);
             Dims {
-                width: b.width + i.width + size * 0.03,
-                ascent: b.ascent,
+                width: b.width + i.width + c.space_after_script * size,
+                ascent: b.ascent.max(i.ascent - shift),
                 descent: b.descent.max(shift + i.descent),
             }
         }
 
         MathNode::SubSup(base, sub, sup) => {
-            let b = measure(font, sf, base, size);
-            let sc = size * 0.65;
-            let ssf = font.as_scaled(PxScale::from(sc));
-            let sp = measure(font, &ssf, sup, sc);
-            let sb = measure(font, &ssf, sub, sc);
-            Dims {
-                width: b.width + sp.width.max(sb.width) + size * 0.03,
-                ascent: b.ascent.max(b.ascent * 0.5 + sp.ascent),
-                descent: b.descent.max(b.descent + b.ascent * 0.2 + sb.descent),
-            }
-        }
-
-        MathNode::Sqrt(content) => {
-            let c = measure(font, sf, content, size);
-            // Radical drawn manually as lines. The diagonal stroke rises from
-            // the valley (below content) to the top of the overline bar.
-            // Hook width + main diagonal width = total radical width.
-            let hook_w = size * 0.15;
-            let diag_w = size * 0.35;
-            let rad_w = hook_w + diag_w;
-            let bar_gap = size * 0.3; // gap between content top and overline
-            let bar_top = c.ascent + bar_gap;
+            let b = measure(font, base, st);
+            let sp = measure(font, sup, st.sup(c));
+            let sb = measure(font, sub, st.sub(c));
+            let (up, down) = sub_sup_shifts(font, is_char_base(base), b, sb, sp, st);
+            let ic = italic_correction(font, base, st);
             Dims {
-                width: rad_w + c.width + size * 0.15,
-                ascent: bar_top,
-                descent: c.descent + size * 0.15,
+                width: b.width + (sp.width + ic).max(sb.width) + c.space_after_script * size,
+                ascent: b.ascent.max(up + sp.ascent).max(sb.ascent - down),
+                descent: b.descent.max(down + sb.descent).max(sp.descent - up),
             }
         }
 
+        MathNode::Sqrt { index, radicand } => place_radical(font, index.as_deref(), radicand, st).dims,
+
         MathNode::Overline(content) => {
-            let c = measure(font, sf, content, size);
+            let cd = measure(font, content, st.cramp());
             Dims {
-                width: c.width,
-                ascent: c.ascent + size * 0.15,
-                descent: c.descent,
+                width: cd.width,
+                ascent: cd.ascent
+                    + (c.overbar_vertical_gap + c.overbar_rule_thickness + c.overbar_extra_ascender) * size,
+                descent: cd.descent,
             }
         }
 
-        MathNode::Accent(_, content) => {
-            let c = measure(font, sf, content, size);
+        MathNode::Underline(content) => {
+            let cd = measure(font, content, st);
             Dims {
-                width: c.width,
-                ascent: c.ascent + size * 0.15,
-                descent: c.descent,
+                width: cd.width,
+                ascent: cd.ascent,
+                descent: cd.descent
+                    + (c.underbar_vertical_gap + c.underbar_rule_thickness + c.underbar_extra_descender) * size,
             }
         }
 
-        MathNode::Matrix { rows, left_delim, right_delim } => {
-            measure_matrix(font, sf, rows, left_delim.is_some(), right_delim.is_some(), size)
+        MathNode::WideAccent(ach, content) => place_wide_accent(font, *ach, content, st).dims,
+        MathNode::Brace { over, content } => place_brace(font, *over, content, st).dims,
+
+        MathNode::Accent(ach, content) => {
+            let cd = measure(font, content, st.cramp());
+            let shift = accent_shift(font, cd, size);
+            let accent = font.ink(font.glyph(*ach)).map_or(0.0, |ink| ink.ascent * size);
+            Dims {
+                width: cd.width,
+                ascent: cd.ascent.max(shift + accent),
+                descent: cd.descent,
+            }
         }
 
-        MathNode::Cases(rows) => {
-            measure_matrix(font, sf, rows, true, false, size)
+        MathNode::Matrix { rows, left_delim, right_delim, spec } => {
+            place_matrix(font, rows, *left_delim, *right_delim, spec, st.cells(c)).dims
         }
 
-        MathNode::Delimited { content, .. } => {
-            let c = measure(font, sf, content, size);
-            let ds = (c.height() + size * 0.2).min(size * 2.5);
-            let dw = ds * 0.3;
-            let inner_pad = size * 0.12;
-            Dims {
-                width: c.width + dw * 2.0 + inner_pad * 2.0,
-                ascent: c.ascent + size * 0.1,
-                descent: c.descent + size * 0.1,
+        MathNode::Cases { rows, display, right_brace } => {
+            let spec = cases_spec();
+            place_cases(font, rows, *display, *right_brace, &spec, st).dims
+        }
+
+        MathNode::Aligned { kind, rows } => place_aligned(font, *kind, rows, st).dims,
+        MathNode::Tagged { content, tags } => place_tags(font, content, tags, st).dims,
+
+        MathNode::BigOp { op, limits, sub, sup } => {
+            place_big_op(font, op, *limits, sub.as_deref(), sup.as_deref(), st).dims
+        }
+
+        MathNode::Style(level, content) => measure(font, content, st.switch(*level, c)),
+        MathNode::Styled(variant, content) => measure(font, content, st.styled(*variant)),
+        MathNode::Class(_, content) | MathNode::Negated(content) | MathNode::Colored(_, content) => {
+            measure(font, content, st)
+        }
+        MathNode::Cancel { content, kind } => place_cancel(font, content, kind, st).dims,
+        MathNode::XArrow { arrow, above, below } => place_xarrow(font, *arrow, above, below.as_deref(), st).dims,
+        MathNode::Boxed { content, frame, .. } => {
+            let d = measure(font, content, st);
+            let pad = box_padding(*frame, size);
+            Dims { width: d.width + 2.0 * pad, ascent: d.ascent + pad, descent: d.descent + pad }
+        }
+
+        MathNode::Delimited { left, right, content, middle } => place_fence(font, *left, content, middle, *right, st).dims,
+        MathNode::BigDelim { delim, size: level } => {
+            let d = big_delim(font, *delim, *level, size);
+            Dims { width: d.width, ascent: d.ascent, descent: d.descent }
+        }
+    }
+}
+
+/// The character a symbol is drawn with in the current math alphabet (math
+/// italic for plain letters), keeping the plain one when the font has no
+/// glyph for the styled letter.
+fn styled_char(font: &MathFont, st: Style, ch: char) -> char {
+    match st.variant.or_else(|| alphabet::default_variant(ch)) {
+        Some(variant) => {
+            let mapped = alphabet::map_char(variant, ch);
+            if font.glyph(mapped) == GlyphId(0) { ch } else { mapped }
+        }
+        None => ch,
+    }
+}
+
+/// Italic correction of a single-glyph base, added before its superscript.
+fn italic_correction(font: &MathFont, base: &MathNode, st: Style) -> f32 {
+    match base {
+        MathNode::Symbol(ch) => font.italic_correction(font.glyph(styled_char(font, st, *ch))) * st.size,
+        _ => 0.0,
+    }
+}
+
+/// Bases made of a single glyph keep scripts at the font's standard shifts;
+/// bigger bases push their scripts out relative to their own box.
+fn is_char_base(base: &MathNode) -> bool {
+    matches!(base, MathNode::Symbol(_))
+}
+
+/// Baseline rise of a superscript `e` attached to a base `b`.
+fn sup_shift(font: &MathFont, char_base: bool, b: Dims, e: Dims, st: Style) -> f32 {
+    let c = &font.constants;
+    let size = st.size;
+    let up = if st.cramped { c.superscript_shift_up_cramped } else { c.superscript_shift_up };
+    let mut shift = (up * size).max(e.descent + c.superscript_bottom_min * size);
+    if !char_base {
+        shift = shift.max(b.ascent - c.superscript_baseline_drop_max * size);
+    }
+    shift
+}
+
+/// Baseline drop of a subscript `i` attached to a base `b`.
+fn sub_shift(font: &MathFont, char_base: bool, b: Dims, i: Dims, st: Style) -> f32 {
+    let c = &font.constants;
+    let size = st.size;
+    let mut shift = (c.subscript_shift_down * size).max(i.ascent - c.subscript_top_max * size);
+    if !char_base {
+        shift = shift.max(b.descent + c.subscript_baseline_drop_min * size);
+    }
+    shift
+}
+
+/// Superscript rise and subscript drop when both scripts are present,
+/// keeping at least `subSuperscriptGapMin` between them.
+fn sub_sup_shifts(font: &MathFont, char_base: bool, b: Dims, sb: Dims, sp: Dims, st: Style) -> (f32, f32) {
+    let c = &font.constants;
+    let size = st.size;
+    let mut up = sup_shift(font, char_base, b, sp, st);
+    let mut down = sub_shift(font, char_base, b, sb, st);
+    let gap = (up - sp.descent) - (sb.ascent - down);
+    let min_gap = c.sub_superscript_gap_min * size;
+    if gap < min_gap {
+        // Raise the superscript as far as allowed, then lower the subscript.
+        let sup_bottom = up - sp.descent;
+        let raise = (c.superscript_bottom_max_with_subscript * size - sup_bottom)
+            .clamp(0.0, min_gap - gap);
+        up += raise;
+        down += min_gap - gap - raise;
+    }
+    (up, down)
+}
+
+/// Baseline rise of a top accent so it clears a base taller than `accentBaseHeight`.
+fn accent_shift(font: &MathFont, base: Dims, size: f32) -> f32 {
+    (base.ascent - font.constants.accent_base_height * size).max(0.0)
+}
+
+/// A glyph stretched across a box `width` wide, with its ink extents.
+struct HorizontalGlyph {
+    glyph: Stretched,
+    /// Left edge of the ink relative to the glyph origin, and its width.
+    ink_left: f32,
+    ink_width: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+fn stretch_across(font: &MathFont, ch: char, width: f32, size: f32) -> HorizontalGlyph {
+    let glyph = font.stretch_horizontal(font.glyph(ch), width / size);
+    let (ink_left, ink_width, ascent, descent) = match &glyph {
+        Stretched::Variant(gid) => font
+            .ink(*gid)
+            .map_or((0.0, 0.0, 0.0, 0.0), |ink| (ink.x_min, ink.x_max - ink.x_min, ink.ascent, ink.descent)),
+        Stretched::Assembly { parts, length } => {
+            let (asc, desc) = parts
+                .iter()
+                .filter_map(|p| font.ink(p.glyph))
+                .fold((0.0f32, 0.0f32), |(a, d), ink| (a.max(ink.ascent), d.max(ink.descent)));
+            (0.0, *length, asc, desc)
+        }
+    };
+    HorizontalGlyph {
+        glyph,
+        ink_left: ink_left * size,
+        ink_width: ink_width * size,
+        ascent: ascent * size,
+        descent: descent * size,
+    }
+}
+
+/// Content with a stretched glyph above or below it, both centred; x offsets
+/// are from the node's origin, `rise` is the glyph baseline's height.
+struct StretchPlacement {
+    dims: Dims,
+    glyph: Stretched,
+    glyph_x: f32,
+    rise: f32,
+    content_x: f32,
+}
+
+fn center_stretched(content: Dims, h: HorizontalGlyph, rise: f32) -> StretchPlacement {
+    let width = content.width.max(h.ink_width);
+    StretchPlacement {
+        dims: Dims {
+            width,
+            ascent: content.ascent.max(rise + h.ascent),
+            descent: content.descent.max(h.descent - rise),
+        },
+        glyph_x: (width - h.ink_width) / 2.0 - h.ink_left,
+        glyph: h.glyph,
+        rise,
+        content_x: (width - content.width) / 2.0,
+    }
+}
+
+fn place_wide_accent(font: &MathFont, ch: char, content: &MathNode, st: Style) -> StretchPlacement {
+    let cd = measure(font, content, st.cramp());
+    let h = stretch_across(font, ch, cd.width, st.size);
+    // Size variants dip lower than the base accent (a wide caron's point
+    // does), so keep their ink clear of the content as well.
+    let clear = cd.ascent + WIDE_ACCENT_GAP * st.size + h.descent;
+    center_stretched(cd, h, accent_shift(font, cd, st.size).max(clear))
+}
+
+fn place_brace(font: &MathFont, over: bool, content: &MathNode, st: Style) -> StretchPlacement {
+    let c = &font.constants;
+    let cd = measure(font, content, st);
+    let h = stretch_across(font, if over { '\u{23DE}' } else { '\u{23DF}' }, cd.width, st.size);
+    let rise = if over {
+        cd.ascent + c.stretch_stack_gap_below_min * st.size + h.descent
+    } else {
+        -(cd.descent + c.stretch_stack_gap_above_min * st.size + h.ascent)
+    };
+    center_stretched(cd, h, rise)
+}
+
+/// The strokes over cancelled content, and where a `\cancelto` value goes.
+/// Points are offsets from the node's origin, with y measured downwards from
+/// the baseline.
+struct CancelPlacement<'a> {
+    dims: Dims,
+    strokes: Vec<((f32, f32), (f32, f32))>,
+    /// The value at the arrow's tip, with its origin.
+    value: Option<(&'a MathNode, f32, f32)>,
+}
+
+fn place_cancel<'a>(font: &MathFont, content: &MathNode, kind: &'a CancelKind, st: Style) -> CancelPlacement<'a> {
+    let c = &font.constants;
+    let size = st.size;
+    let mut dims = measure(font, content, st);
+    let (bottom_left, top_right) = ((0.0, dims.descent), (dims.width, -dims.ascent));
+    let (top_left, bottom_right) = ((0.0, -dims.ascent), (dims.width, dims.descent));
+    let mut value = None;
+    let strokes = match kind {
+        CancelKind::Forward => vec![(bottom_left, top_right)],
+        CancelKind::Back => vec![(top_left, bottom_right)],
+        CancelKind::Cross => vec![(bottom_left, top_right), (top_left, bottom_right)],
+        CancelKind::To(to) => {
+            // Carry on along the diagonal past the top right corner.
+            let (dx, dy) = (dims.width, -dims.height());
+            let len = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+            let (ux, uy) = (dx / len, dy / len);
+            let tip = (top_right.0 + ux * CANCELTO_OVERSHOOT * size, top_right.1 + uy * CANCELTO_OVERSHOOT * size);
+            let head = CANCELTO_HEAD * size;
+            // Barbs about 27° either side of the shaft.
+            let (cos, sin) = (0.9f32, 0.45f32);
+            let barb = |sin: f32| (tip.0 - head * (ux * cos - uy * sin), tip.1 - head * (uy * cos + ux * sin));
+
+            let vd = measure(font, to, st.sup(c));
+            let (vx, vy) = (tip.0, tip.1 - vd.descent);
+            dims.width = dims.width.max(vx + vd.width);
+            dims.ascent = dims.ascent.max(vd.ascent - vy);
+            value = Some((to.as_ref(), vx, vy));
+            vec![(bottom_left, tip), (tip, barb(sin)), (tip, barb(-sin))]
+        }
+    };
+    CancelPlacement { dims, strokes, value }
+}
+
+/// An extensible arrow and its labels; x offsets are from the node's origin,
+/// `rise` and `drop` are the label baselines' distances above and below
+/// the arrow's baseline.
+struct XArrowPlacement {
+    dims: Dims,
+    /// The stretched arrow and its x offset, or `None` when the font cannot
+    /// stretch it and it is drawn with lines across the whole width.
+    glyph: Option<(Stretched, f32)>,
+    above: (f32, f32),
+    below: Option<(f32, f32)>,
+}
+
+fn place_xarrow(font: &MathFont, arrow: char, above: &MathNode, below: Option<&MathNode>, st: Style) -> XArrowPlacement {
+    let c = &font.constants;
+    let size = st.size;
+    let a = measure(font, above, st.sup(c));
+    let b = below.map(|n| measure(font, n, st.sub(c)));
+    let labels = a.width.max(b.map_or(0.0, |b| b.width));
+    let target = (labels + 2.0 * XARROW_PAD * size).max(font.advance(font.glyph(arrow)) * size);
+
+    let h = stretch_across(font, arrow, target, size);
+    let (glyph, width, arrow_ascent, arrow_descent) = match line_arrow(arrow) {
+        Some(shafts) if h.ink_width < target => {
+            let axis = c.axis_height * size;
+            let top = shafts.iter().map(|s| s.0).fold(f32::MIN, f32::max) + LINE_ARROW_BARB;
+            let bottom = shafts.iter().map(|s| s.0).fold(f32::MAX, f32::min) - LINE_ARROW_BARB;
+            (None, target, axis + top * size, -(axis + bottom * size))
+        }
+        _ => {
+            let width = h.ink_width.max(labels);
+            let glyph_x = (width - h.ink_width) / 2.0 - h.ink_left;
+            (Some((h.glyph, glyph_x)), width, h.ascent, h.descent)
+        }
+    };
+
+    let rise = arrow_ascent + c.stretch_stack_gap_below_min * size + a.descent;
+    let mut dims = Dims { width, ascent: arrow_ascent.max(rise + a.ascent), descent: arrow_descent };
+    let below = b.map(|b| {
+        let drop = arrow_descent + c.stretch_stack_gap_above_min * size + b.ascent;
+        dims.descent = dims.descent.max(drop + b.descent);
+        ((width - b.width) / 2.0, drop)
+    });
+    XArrowPlacement { dims, glyph, above: ((width - a.width) / 2.0, rise), below }
+}
+
+/// The end of an arrow shaft drawn with lines.
+#[derive(Clone, Copy)]
+enum Barb {
+    Plain,
+    /// A half head, above or below the shaft (harpoons).
+    Up,
+    Down,
+    /// A full arrowhead.
+    Head,
+    /// A short upright stroke, the tail of `\mapsto`.
+    Bar,
+    /// A half loop curling up over the shaft, the tail of the hook arrows.
+    Hook,
+}
+
+/// Arrows the font may not stretch wide enough, as shafts to draw with
+/// lines: each one's height above the axis (in units of the font size) and
+/// its left and right ends.
+fn line_arrow(arrow: char) -> Option<&'static [(f32, Barb, Barb)]> {
+    let shafts: &[(f32, Barb, Barb)] = match arrow {
+        '=' => &[(0.1, Barb::Plain, Barb::Plain), (-0.1, Barb::Plain, Barb::Plain)],
+        '\u{21CC}' => &[(0.12, Barb::Plain, Barb::Up), (-0.12, Barb::Down, Barb::Plain)],
+        '\u{21CB}' => &[(0.12, Barb::Up, Barb::Plain), (-0.12, Barb::Plain, Barb::Down)],
+        '\u{2192}' => &[(0.0, Barb::Plain, Barb::Head)],
+        '\u{2190}' => &[(0.0, Barb::Head, Barb::Plain)],
+        '\u{2194}' => &[(0.0, Barb::Head, Barb::Head)],
+        '\u{21A6}' => &[(0.0, Barb::Bar, Barb::Head)],
+        '\u{21A9}' => &[(0.0, Barb::Head, Barb::Hook)],
+        '\u{21AA}' => &[(0.0, Barb::Hook, Barb::Head)],
+        '\u{21C0}' => &[(0.0, Barb::Plain, Barb::Up)],
+        '\u{21C1}' => &[(0.0, Barb::Plain, Barb::Down)],
+        '\u{21BC}' => &[(0.0, Barb::Up, Barb::Plain)],
+        '\u{21BD}' => &[(0.0, Barb::Down, Barb::Plain)],
+        _ => return None,
+    };
+    Some(shafts)
+}
+
+fn layout_line_arrow(font: &MathFont, arrow: char, width: f32, size: f32, x: f32, by: f32, cmds: &mut Vec<DrawCmd>) {
+    let c = &font.constants;
+    let thickness = c.fraction_rule_thickness * size;
+    let (head, barb) = (LINE_ARROW_HEAD * size, LINE_ARROW_BARB * size);
+    for &(rise, left, right) in line_arrow(arrow).unwrap_or_default() {
+        let y = by - (c.axis_height + rise) * size;
+        cmds.push(DrawCmd::HLine { x, y, width, thickness, color: None });
+        // `inward` points from the tip along the shaft.
+        for (end, inward, tip) in [(left, 1.0, x), (right, -1.0, x + width)] {
+            let strokes: Vec<(f32, f32, f32, f32)> = match end {
+                Barb::Plain => Vec::new(),
+                Barb::Up => vec![(tip, y, tip + inward * head, y - barb)],
+                Barb::Down => vec![(tip, y, tip + inward * head, y + barb)],
+                Barb::Head => vec![(tip, y, tip + inward * head, y - barb), (tip, y, tip + inward * head, y + barb)],
+                Barb::Bar => vec![(tip, y - barb, tip, y + barb)],
+                Barb::Hook => {
+                    // A half circle bulging outwards from the shaft's end.
+                    let r = barb / 2.0;
+                    let point = |i: usize| {
+                        let a = std::f32::consts::PI * i as f32 / 8.0;
+                        (tip - inward * r * a.sin(), y - r + r * a.cos())
+                    };
+                    (0..8)
+                        .map(|i| {
+                            let ((x1, y1), (x2, y2)) = (point(i), point(i + 1));
+                            (x1, y1, x2, y2)
+                        })
+                        .collect()
+                }
+            };
+            for (x1, y1, x2, y2) in strokes {
+                cmds.push(DrawCmd::Line { x1, y1, x2, y2, thickness, color: None });
             }
         }
     }
 }
 
-fn measure_matrix(
-    font: &FontRef,
-    sf: &ab_glyph::PxScaleFont<&FontRef>,
-    rows: &[Vec<MathNode>],
-    has_left: bool,
-    has_right: bool,
-    size: f32,
-) -> Dims {
-    if rows.is_empty() {
-        return Dims { width: 0.0, ascent: 0.0, descent: 0.0 };
+/// Gap between a radicand and the radical's rule.
+fn radical_gap(font: &MathFont, st: Style) -> f32 {
+    let c = &font.constants;
+    let gap = if st.is_display() { c.radical_display_style_vertical_gap } else { c.radical_vertical_gap };
+    gap * st.size
+}
+
+/// A big operator with its limits; offsets are relative to the node's origin.
+struct OpPlacement {
+    dims: Dims,
+    op_x: f32,
+    /// Glyph of a symbol operator and how far its baseline is lowered to
+    /// centre it on the math axis. `None` for named operators.
+    glyph: Option<(GlyphId, f32)>,
+    /// Superscript (x, baseline rise) and subscript (x, baseline drop).
+    sup: Option<(f32, f32)>,
+    sub: Option<(f32, f32)>,
+}
+
+fn place_big_op(
+    font: &MathFont,
+    op: &MathNode,
+    limits: Limits,
+    sub: Option<&MathNode>,
+    sup: Option<&MathNode>,
+    st: Style,
+) -> OpPlacement {
+    let c = &font.constants;
+    let size = st.size;
+    let (od, glyph, ic) = match op {
+        MathNode::Symbol(ch) => {
+            let mut gid = font.glyph(*ch);
+            if st.is_display() {
+                gid = font.vertical_variant(gid, c.display_operator_min_height);
+            }
+            let (asc, desc) = font.ink(gid).map_or((0.0, 0.0), |ink| (ink.ascent, ink.descent));
+            let drop = (asc - desc) / 2.0 * size - c.axis_height * size;
+            let od = Dims {
+                width: font.advance(gid) * size,
+                ascent: asc * size - drop,
+                descent: desc * size + drop,
+            };
+            (od, Some((gid, drop)), font.italic_correction(gid) * size)
+        }
+        other => (measure(font, other, st), None, 0.0),
+    };
+
+    let spd = sup.map(|n| measure(font, n, st.sup(c)));
+    let sbd = sub.map(|n| measure(font, n, st.sub(c)));
+
+    let stacked = match limits {
+        Limits::Always => true,
+        Limits::Display => st.is_display(),
+        Limits::Never => false,
+    };
+
+    if stacked {
+        let width = od.width
+            .max(spd.map_or(0.0, |d| d.width))
+            .max(sbd.map_or(0.0, |d| d.width));
+        let mut dims = Dims { width, ..od };
+        let sup = spd.map(|sp| {
+            let gap = (c.upper_limit_gap_min * size)
+                .max(c.upper_limit_baseline_rise_min * size - sp.descent);
+            let rise = od.ascent + gap + sp.descent;
+            dims.ascent = dims.ascent.max(rise + sp.ascent);
+            ((width - sp.width + ic) / 2.0, rise)
+        });
+        let sub = sbd.map(|sb| {
+            let gap = (c.lower_limit_gap_min * size)
+                .max(c.lower_limit_baseline_drop_min * size - sb.ascent);
+            let drop = od.descent + gap + sb.ascent;
+            dims.descent = dims.descent.max(drop + sb.descent);
+            ((width - sb.width - ic) / 2.0, drop)
+        });
+        return OpPlacement { dims, op_x: (width - od.width) / 2.0, glyph, sup, sub };
     }
-    let ncols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
-    let gap_x = size * 0.6;
-    let gap_y = size * 0.3;
-    let dw = size * 0.3;
 
-    let mut col_w = vec![0.0f32; ncols];
-    let mut row_h = Vec::new();
+    // Side scripts, placed like those of any other non-character base.
+    let (up, down) = match (sbd, spd) {
+        (Some(sb), Some(sp)) => sub_sup_shifts(font, false, od, sb, sp, st),
+        (None, Some(sp)) => (sup_shift(font, false, od, sp, st), 0.0),
+        (Some(sb), None) => (0.0, sub_shift(font, false, od, sb, st)),
+        (None, None) => (0.0, 0.0),
+    };
+    let mut dims = od;
+    let mut scripts_w = 0.0f32;
+    if let Some(sp) = spd {
+        scripts_w = scripts_w.max(ic + sp.width);
+        dims.ascent = dims.ascent.max(up + sp.ascent);
+        dims.descent = dims.descent.max(sp.descent - up);
+    }
+    if let Some(sb) = sbd {
+        scripts_w = scripts_w.max(sb.width);
+        dims.ascent = dims.ascent.max(sb.ascent - down);
+        dims.descent = dims.descent.max(down + sb.descent);
+    }
+    if spd.is_some() || sbd.is_some() {
+        dims.width += scripts_w + c.space_after_script * size;
+    }
+    OpPlacement {
+        dims,
+        op_x: 0.0,
+        glyph,
+        sup: spd.map(|_| (od.width + ic, up)),
+        sub: sbd.map(|_| (od.width, down)),
+    }
+}
 
-    for row in rows {
-        let mut ra = size * 0.4;
-        let mut rd = size * 0.2;
-        for (j, cell) in row.iter().enumerate() {
-            let d = measure(font, sf, cell, size);
-            if j < ncols { col_w[j] = col_w[j].max(d.width); }
-            ra = ra.max(d.ascent);
-            rd = rd.max(d.descent);
+/// A delimiter grown to cover some content, centred on the math axis.
+/// `glyph` is `None` for the null delimiter `.`, which only takes up space.
+struct Delim {
+    glyph: Option<Stretched>,
+    width: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+/// Pick the size variant or assembly of `ch` that covers `cover` (TeX rule 19:
+/// at least `\delimiterfactor` of the content, at most `\delimitershortfall` short).
+fn make_delim(font: &MathFont, ch: char, cover: Dims, size: f32) -> Delim {
+    if ch == '\0' {
+        return Delim { glyph: None, width: NULL_DELIMITER_SPACE * size, ascent: 0.0, descent: 0.0 };
+    }
+    let axis = font.constants.axis_height * size;
+    let delta = 2.0 * (cover.ascent - axis).max(cover.descent + axis);
+    let target = (delta * DELIMITER_FACTOR).max(delta - DELIMITER_SHORTFALL * size);
+    sized_delim(font, ch, target, size)
+}
+
+/// The size variant or assembly of `ch` at least `height` tall, centred on
+/// the axis.
+fn sized_delim(font: &MathFont, ch: char, height: f32, size: f32) -> Delim {
+    let axis = font.constants.axis_height * size;
+    let stretched = font.stretch_vertical(font.glyph(ch), height / size);
+    let (width, height) = stretched_size(font, &stretched);
+    Delim {
+        glyph: Some(stretched),
+        width: width * size,
+        ascent: axis + height * size / 2.0,
+        descent: height * size / 2.0 - axis,
+    }
+}
+
+/// Advance width and ink height of a vertically stretched glyph, in units of the font size.
+fn stretched_size(font: &MathFont, stretched: &Stretched) -> (f32, f32) {
+    match stretched {
+        Stretched::Variant(gid) => {
+            let h = font.ink(*gid).map_or(0.0, |ink| ink.ascent + ink.descent);
+            (font.advance(*gid), h)
+        }
+        Stretched::Assembly { parts, length } => {
+            let w = parts.iter().map(|p| font.advance(p.glyph)).fold(0.0, f32::max);
+            (w, *length)
+        }
+    }
+}
+
+/// A radical sign grown to its radicand, with the optional index; x offsets
+/// are from the node's origin, heights measured upwards from the baseline.
+struct RadicalPlacement {
+    dims: Dims,
+    glyph: Stretched,
+    glyph_x: f32,
+    /// Height of the bottom of the radical sign's ink (negative below the baseline).
+    glyph_bottom: f32,
+    radicand_x: f32,
+    /// Top of the rule over the radicand, and its thickness.
+    rule_top: f32,
+    rule: f32,
+    /// Index (x, baseline rise).
+    index: Option<(f32, f32)>,
+}
+
+fn place_radical(font: &MathFont, index: Option<&MathNode>, radicand: &MathNode, st: Style) -> RadicalPlacement {
+    let c = &font.constants;
+    let size = st.size;
+    let body = measure(font, radicand, st.cramp());
+    let rule = c.radical_rule_thickness * size;
+    let mut gap = radical_gap(font, st);
+
+    let target = body.height() + gap + rule;
+    let glyph = font.stretch_vertical(font.glyph('\u{221A}'), target / size);
+    let (glyph_w, glyph_h) = stretched_size(font, &glyph);
+    let (glyph_w, glyph_h) = (glyph_w * size, glyph_h * size);
+    // Share any extra height of the sign between the gaps above and below the radicand.
+    if glyph_h > target {
+        gap += (glyph_h - target) / 2.0;
+    }
+    let rule_top = body.ascent + gap + rule;
+    let glyph_bottom = rule_top - glyph_h;
+
+    let mut dims = Dims {
+        width: glyph_w + body.width,
+        ascent: rule_top + c.radical_extra_ascender * size,
+        descent: body.descent.max(-glyph_bottom),
+    };
+    let mut glyph_x = 0.0;
+    let index = index.map(|n| {
+        let d = measure(font, n, st.switch(MathStyle::ScriptScript, c));
+        let before = c.radical_kern_before_degree * size;
+        let after = c.radical_kern_after_degree * size;
+        // The index may tuck into the sign's crook, but never hang off its left side.
+        let index_x = before.max(-(d.width + after).min(0.0));
+        glyph_x = (index_x + d.width + after).max(0.0);
+        let rise = glyph_bottom + c.radical_degree_bottom_raise_percent * glyph_h + d.descent;
+        dims.width += glyph_x;
+        dims.ascent = dims.ascent.max(rise + d.ascent);
+        dims.descent = dims.descent.max(d.descent - rise);
+        (index_x, rise)
+    });
+
+    RadicalPlacement {
+        dims,
+        glyph,
+        glyph_x,
+        glyph_bottom,
+        radicand_x: glyph_x + glyph_w,
+        rule_top,
+        rule,
+        index,
+    }
+}
+
+/// Dims of content wrapped in a left and a right delimiter.
+/// `\left ... \middle ... \right`: every delimiter is sized to cover all
+/// the content, which the `\middle` ones split into separate segments.
+struct FencePlacement<'a> {
+    dims: Dims,
+    delims: Vec<(Delim, f32)>,
+    segments: Vec<(&'a MathNode, f32)>,
+}
+
+fn place_fence<'a>(
+    font: &MathFont,
+    left: char,
+    content: &'a MathNode,
+    middle: &'a [(char, MathNode)],
+    right: char,
+    st: Style,
+) -> FencePlacement<'a> {
+    let size = st.size;
+    let segments: Vec<(&MathNode, Dims)> = std::iter::once(content)
+        .chain(middle.iter().map(|(_, segment)| segment))
+        .map(|segment| (segment, measure(font, segment, st)))
+        .collect();
+    let cover = segments.iter().fold(Dims { width: 0.0, ascent: 0.0, descent: 0.0 }, |acc, (_, d)| Dims {
+        width: acc.width + d.width,
+        ascent: acc.ascent.max(d.ascent),
+        descent: acc.descent.max(d.descent),
+    });
+
+    let chars = std::iter::once(left).chain(middle.iter().map(|(ch, _)| *ch)).chain(std::iter::once(right));
+    let mut dims = cover;
+    let mut placed_delims = Vec::with_capacity(middle.len() + 2);
+    let mut placed_segments = Vec::with_capacity(segments.len());
+    let mut x = 0.0;
+    for (i, ch) in chars.enumerate() {
+        let delim = make_delim(font, ch, cover, size);
+        dims.ascent = dims.ascent.max(delim.ascent);
+        dims.descent = dims.descent.max(delim.descent);
+        let width = delim.width;
+        placed_delims.push((delim, x));
+        x += width;
+        if let Some((segment, d)) = segments.get(i) {
+            placed_segments.push((*segment, x));
+            x += d.width;
         }
-        row_h.push((ra, rd));
     }
+    dims.width = x;
+    FencePlacement { dims, delims: placed_delims, segments: placed_segments }
+}
 
-    let tw: f32 = col_w.iter().sum::<f32>()
-        + gap_x * ncols.saturating_sub(1) as f32
-        + if has_left { dw } else { 0.0 }
-        + if has_right { dw } else { 0.0 }
-        + size * 0.2;
+/// Delimiter of a fixed `\big`..`\Bigg` size, centred on the axis. amsmath
+/// sets \delimiterfactor to 1000 and \delimitershortfall to zero here, so the
+/// glyph covers the full height. A null delimiter takes no space here.
+fn big_delim(font: &MathFont, ch: char, level: u8, size: f32) -> Delim {
+    if ch == '\0' {
+        return Delim { glyph: None, width: 0.0, ascent: 0.0, descent: 0.0 };
+    }
+    sized_delim(font, ch, BIG_DELIM_HEIGHTS[(level.clamp(1, 4) - 1) as usize] * size, size)
+}
 
-    let th: f32 = row_h.iter().map(|(a, d)| a + d).sum::<f32>()
-        + gap_y * rows.len().saturating_sub(1) as f32;
+/// Delimiter covering `height` centred on the axis.
+fn axis_delim(font: &MathFont, ch: char, height: f32, size: f32) -> Delim {
+    let axis = font.constants.axis_height * size;
+    let cover = Dims { width: 0.0, ascent: axis + height / 2.0, descent: height / 2.0 - axis };
+    make_delim(font, ch, cover, size)
+}
 
+fn fence_dims(content: Dims, left: &Delim, right: &Delim) -> Dims {
     Dims {
-        width: tw,
-        ascent: th / 2.0 + size * 0.15,
-        descent: th / 2.0 - size * 0.15,
+        width: left.width + content.width + right.width,
+        ascent: content.ascent.max(left.ascent).max(right.ascent),
+        descent: content.descent.max(left.descent).max(right.descent),
+    }
+}
+
+/// A fraction or rule-less stack laid out: x offsets from the node's
+/// origin, shifts measured from the baseline.
+struct FracPlacement {
+    dims: Dims,
+    left: Delim,
+    right: Delim,
+    /// Start and width of the rule (and of the column the parts are centred in).
+    body_x: f32,
+    body_width: f32,
+    axis: f32,
+    /// Rule thickness; zero for `\binom`, `\atop` and the like.
+    rule: f32,
+    num_shift: f32,
+    den_shift: f32,
+}
+
+fn place_frac(
+    font: &MathFont,
+    num: &MathNode,
+    den: &MathNode,
+    rule: Option<f32>,
+    left: char,
+    right: char,
+    st: Style,
+) -> FracPlacement {
+    let c = &font.constants;
+    let size = st.size;
+    let n = measure(font, num, st.num(c));
+    let d = measure(font, den, st.den(c));
+    let axis = c.axis_height * size;
+    let rule = rule.unwrap_or(c.fraction_rule_thickness).max(0.0) * size;
+
+    let (num_shift, den_shift) = if rule == 0.0 {
+        // TeX rule 15c: stack the parts, then push them apart evenly if they
+        // come closer than the minimum gap.
+        let (up, down, gap_min) = if st.is_display() {
+            (
+                c.stack_top_display_style_shift_up,
+                c.stack_bottom_display_style_shift_down,
+                c.stack_display_style_gap_min,
+            )
+        } else {
+            (c.stack_top_shift_up, c.stack_bottom_shift_down, c.stack_gap_min)
+        };
+        let (up, down) = (up * size, down * size);
+        let gap = (up - n.descent) - (d.ascent - down);
+        let extra = (gap_min * size - gap).max(0.0) / 2.0;
+        (up + extra, down + extra)
+    } else {
+        let (num_up, num_gap, den_down, den_gap) = if st.is_display() {
+            (
+                c.fraction_numerator_display_style_shift_up,
+                c.fraction_num_display_style_gap_min,
+                c.fraction_denominator_display_style_shift_down,
+                c.fraction_denom_display_style_gap_min,
+            )
+        } else {
+            (
+                c.fraction_numerator_shift_up,
+                c.fraction_numerator_gap_min,
+                c.fraction_denominator_shift_down,
+                c.fraction_denominator_gap_min,
+            )
+        };
+        (
+            (num_up * size).max(axis + rule / 2.0 + num_gap * size + n.descent),
+            (den_down * size).max(rule / 2.0 - axis + den_gap * size + d.ascent),
+        )
+    };
+
+    let delim_height = if st.is_display() { FRAC_DELIM_DISPLAY } else { FRAC_DELIM_TEXT } * size;
+    let left = axis_delim(font, left, delim_height, size);
+    let right = axis_delim(font, right, delim_height, size);
+    let body_width = n.width.max(d.width);
+    let body = Dims { width: body_width, ascent: num_shift + n.ascent, descent: den_shift + d.descent };
+    FracPlacement {
+        dims: fence_dims(body, &left, &right),
+        body_x: left.width,
+        body_width,
+        left,
+        right,
+        axis,
+        rule,
+        num_shift,
+        den_shift,
     }
 }
 
-fn measure_char(sf: &ab_glyph::PxScaleFont<&FontRef>, ch: char, size: f32) -> Dims {
-    let gid = sf.font().glyph_id(ch);
+/// A matrix or `array` laid out: cell, separator and rule positions as
+/// offsets from the node's origin (x) and the top of the cell grid (y).
+struct MatrixPlacement<'a> {
+    dims: Dims,
+    cell_style: Style,
+    /// Stretched delimiters and the grid's size, when there are delimiters.
+    fence: Option<(Delim, Delim)>,
+    body: Dims,
+    cell_x: Vec<Vec<f32>>,
+    /// Width of the columns each cell spans.
+    cell_w: Vec<Vec<f32>>,
+    baselines: Vec<f32>,
+    /// Height of each row above and below its baseline.
+    extents: Vec<(f32, f32)>,
+    /// `@{..}` material, repeated on every row.
+    separators: Vec<(&'a MathNode, f32)>,
+    /// Centre lines of the vertical rules, which run the full height.
+    vrules: Vec<f32>,
+    /// Horizontal rules: left end, width and centre line.
+    hrules: Vec<(f32, f32, f32)>,
+}
+
+fn place_matrix<'a>(
+    font: &MathFont,
+    rows: &[Vec<MathNode>],
+    left: Option<char>,
+    right: Option<char>,
+    spec: &'a ArraySpec,
+    st: Style,
+) -> MatrixPlacement<'a> {
+    let size = st.size;
+    let cell_style = if spec.small { st.switch(MathStyle::Script, &font.constants) } else { st };
+    let cells: Vec<Vec<Dims>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| measure(font, cell, cell_style)).collect())
+        .collect();
+    // First column of each cell; `\hdotsfor` cells take up several.
+    let first_col: Vec<Vec<usize>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .scan(0, |j, cell| {
+                    *j += column_span(cell);
+                    Some(*j - column_span(cell))
+                })
+                .collect()
+        })
+        .collect();
+    let ncols = rows
+        .iter()
+        .map(|row| row.iter().map(column_span).sum())
+        .max()
+        .unwrap_or(0)
+        .max(spec.columns.len());
+    let mut col_w = vec![0.0f32; ncols];
+    for ((row, cols), dims) in rows.iter().zip(&first_col).zip(&cells) {
+        for ((cell, &j), d) in row.iter().zip(cols).zip(dims) {
+            if column_span(cell) == 1 {
+                col_w[j] = col_w[j].max(d.width);
+            }
+        }
+    }
+
+    // Columns are padded by \arraycolsep on each side, except next to an
+    // `@{..}`. The `matrix` family has no spec and no padding at its edges;
+    // `smallmatrix` sets its columns a thick space apart instead.
+    let pad = if spec.small {
+        SMALLMATRIX_COL_GAP / 2.0 * size
+    } else {
+        spec.column_sep.unwrap_or(MATRIX_COL_GAP / 2.0) * size
+    };
+    let rule = ARRAY_RULE_WIDTH * size;
+    let rule_sep = DOUBLE_RULE_SEP * size;
+    let mut col_x = Vec::with_capacity(ncols);
+    let mut col_edges = vec![(0.0f32, 0.0f32); ncols];
+    let mut separators = Vec::new();
+    let mut vrules = Vec::new();
+    let mut x = 0.0;
+    for k in 0..=ncols {
+        let boundary = spec.boundaries.get(k);
+        let padded = match boundary {
+            Some(b) => b.separator.is_none(),
+            None => !spec.boundaries.is_empty() || (k > 0 && k < ncols),
+        };
+        if k > 0 {
+            if padded {
+                x += pad;
+            }
+            col_edges[k - 1].1 = x;
+        }
+        if let Some(b) = boundary {
+            for r in 0..b.rules {
+                if r > 0 {
+                    x += rule_sep;
+                }
+                vrules.push(x + rule / 2.0);
+                x += rule;
+            }
+            if let Some(sep) = &b.separator {
+                separators.push((sep, x));
+                x += measure(font, sep, cell_style).width;
+            }
+        }
+        if k < ncols {
+            col_edges[k].0 = x;
+            if padded {
+                x += pad;
+            }
+            col_x.push(x);
+            x += col_w[k];
+        }
+    }
+    let width = x;
+
+    // Rows get at least the height of a strut, scaled by \arraystretch; each
+    // \hline adds its thickness, while consecutive \cline rules share one line.
+    let strut = cell_style.size * spec.stretch.unwrap_or(1.0);
+    let mut hrules = Vec::new();
+    let mut baselines = Vec::with_capacity(rows.len());
+    let mut extents = Vec::with_capacity(rows.len());
+    let mut height = 0.0f32;
+    for i in 0..=rows.len() {
+        let mut previous: Option<&HRule> = None;
+        for r in spec.hlines.get(i).into_iter().flatten() {
+            let shared = matches!((previous, r), (Some(HRule::Columns(_)), HRule::Columns(_)));
+            if !shared {
+                if previous.is_some() {
+                    height += rule_sep;
+                }
+                height += rule;
+            }
+            let (x0, x1) = match r {
+                HRule::Full => (0.0, width),
+                HRule::Columns(range) => {
+                    let last = range.end.min(ncols);
+                    if range.start >= last {
+                        continue;
+                    }
+                    (col_edges[range.start].0, col_edges[last - 1].1)
+                }
+            };
+            hrules.push((x0, x1 - x0, height - rule / 2.0));
+            previous = Some(r);
+        }
+        if let Some(row) = cells.get(i) {
+            let ra = row.iter().fold(MATRIX_STRUT_ASCENT * strut, |a, d| a.max(d.ascent));
+            let rd = row.iter().fold(MATRIX_STRUT_DESCENT * strut, |a, d| a.max(d.descent));
+            baselines.push(height + ra);
+            extents.push((ra, rd));
+            height += ra + rd;
+        }
+    }
+
+    let axis = font.constants.axis_height * size;
+    let body = Dims { width, ascent: height / 2.0 + axis, descent: height / 2.0 - axis };
+    let (dims, fence, shift) = match (left, right) {
+        (None, None) => (body, None, 0.0),
+        _ => {
+            let ld = make_delim(font, left.unwrap_or('\0'), body, size);
+            let rd = make_delim(font, right.unwrap_or('\0'), body, size);
+            let shift = ld.width;
+            (fence_dims(body, &ld, &rd), Some((ld, rd)), shift)
+        }
+    };
+    let mut cell_x = Vec::with_capacity(rows.len());
+    let mut cell_w = Vec::with_capacity(rows.len());
+    for ((row, cols), dims) in rows.iter().zip(&first_col).zip(&cells) {
+        let (xs, ws) = row
+            .iter()
+            .zip(cols)
+            .zip(dims)
+            .map(|((cell, &j), d)| {
+                let last = j + column_span(cell) - 1;
+                let w = col_x[last] + col_w[last] - col_x[j];
+                let align = match spec.columns.get(j) {
+                    _ if column_span(cell) > 1 => 0.0,
+                    Some(ColumnAlign::Left) => 0.0,
+                    Some(ColumnAlign::Right) => 1.0,
+                    _ => 0.5,
+                };
+                (shift + col_x[j] + (w - d.width) * align, w)
+            })
+            .unzip();
+        cell_x.push(xs);
+        cell_w.push(ws);
+    }
+
+    MatrixPlacement {
+        dims,
+        cell_style,
+        fence,
+        body,
+        cell_x,
+        cell_w,
+        baselines,
+        extents,
+        separators: separators.into_iter().map(|(sep, x)| (sep, x + shift)).collect(),
+        vrules: vrules.into_iter().map(|x| x + shift).collect(),
+        hrules: hrules.into_iter().map(|(x, w, y)| (x + shift, w, y)).collect(),
+    }
+}
+
+/// Columns of `cases`, as amsmath sets them: `{@{}l@{\quad}l@{}}` with
+/// rows stretched by 1.2.
+fn cases_spec() -> ArraySpec {
+    let separator = |em| Boundary { rules: 0, separator: Some(MathNode::Space(em)) };
+    ArraySpec {
+        columns: vec![ColumnAlign::Left, ColumnAlign::Left],
+        boundaries: vec![separator(0.0), separator(1.0), separator(0.0)],
+        stretch: Some(CASES_STRETCH),
+        ..ArraySpec::default()
+    }
+}
+
+fn place_cases<'a>(
+    font: &MathFont,
+    rows: &[Vec<MathNode>],
+    display: bool,
+    right_brace: bool,
+    spec: &'a ArraySpec,
+    st: Style,
+) -> MatrixPlacement<'a> {
+    let level = if display { MathStyle::Display } else { MathStyle::Text };
+    let (left, right) = if right_brace { (None, Some('}')) } else { (Some('{'), None) };
+    place_matrix(font, rows, left, right, spec, st.switch(level, &font.constants))
+}
+
+/// Cell positions of an alignment: x offsets from the node's origin, and
+/// each row's baseline below the top of the node.
+struct AlignedPlacement {
+    dims: Dims,
+    cell_style: Style,
+    cell_x: Vec<Vec<f32>>,
+    baselines: Vec<f32>,
+}
+
+fn place_aligned(font: &MathFont, kind: AlignKind, rows: &[Vec<MathNode>], st: Style) -> AlignedPlacement {
+    let c = &font.constants;
+    let size = st.size;
+    // Alignments set their cells in display style, even inside inline math.
+    let cell_style = st.switch(MathStyle::Display, c);
+    let dims: Vec<Vec<Dims>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| measure(font, cell, cell_style)).collect())
+        .collect();
+
+    let mut baselines = Vec::with_capacity(rows.len());
+    let mut height = 0.0f32;
+    for (i, row) in dims.iter().enumerate() {
+        let ra = row.iter().fold(MATRIX_STRUT_ASCENT * size, |a, d| a.max(d.ascent));
+        let rd = row.iter().fold(MATRIX_STRUT_DESCENT * size, |a, d| a.max(d.descent));
+        if i > 0 {
+            height += ALIGN_JOT * size;
+        }
+        baselines.push(height + ra);
+        height += ra + rd;
+    }
+
+    let (width, cell_x) = match kind {
+        AlignKind::Align | AlignKind::EqnArray => {
+            let ncols = dims.iter().map(Vec::len).max().unwrap_or(0);
+            let mut col_w = vec![0.0f32; ncols];
+            for row in &dims {
+                for (j, d) in row.iter().enumerate() {
+                    col_w[j] = col_w[j].max(d.width);
+                }
+            }
+            // Gap before each column, then the column's left edge.
+            let gap = |j: usize| match kind {
+                AlignKind::EqnArray if j > 0 => MATRIX_COL_GAP * size,
+                AlignKind::Align if j > 0 && j.is_multiple_of(2) => ALIGN_COLUMN_GAP * size,
+                _ => 0.0,
+            };
+            let mut col_x = Vec::with_capacity(ncols);
+            let mut x = 0.0;
+            for (j, w) in col_w.iter().enumerate() {
+                x += gap(j);
+                col_x.push(x);
+                x += w;
+            }
+            let cell_x = dims
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .enumerate()
+                        .map(|(j, d)| {
+                            let slack = col_w[j] - d.width;
+                            let align = match kind {
+                                AlignKind::EqnArray => [1.0, 0.5, 0.0][j % 3],
+                                _ => [1.0, 0.0][j % 2],
+                            };
+                            col_x[j] + slack * align
+                        })
+                        .collect()
+                })
+                .collect();
+            (x, cell_x)
+        }
+        AlignKind::Gather | AlignKind::Multline => {
+            let row_w: Vec<f32> = dims.iter().map(|row| row.iter().map(|d| d.width).sum()).collect();
+            let last = row_w.len().saturating_sub(1);
+            let staggered = kind == AlignKind::Multline && last > 0;
+            let gap = MULTLINE_GAP * size;
+            let width = row_w.iter().enumerate().fold(0.0f32, |w, (i, rw)| {
+                let indent = if staggered && (i == 0 || i == last) { gap } else { 0.0 };
+                w.max(rw + indent)
+            });
+            let cell_x = dims
+                .iter()
+                .zip(&row_w)
+                .enumerate()
+                .map(|(i, (row, rw))| {
+                    let mut x = match i {
+                        0 if staggered => 0.0,
+                        i if staggered && i == last => width - rw,
+                        _ => (width - rw) / 2.0,
+                    };
+                    row.iter()
+                        .map(|d| {
+                            x += d.width;
+                            x - d.width
+                        })
+                        .collect()
+                })
+                .collect();
+            (width, cell_x)
+        }
+    };
+
+    let axis = c.axis_height * size;
+    AlignedPlacement {
+        dims: Dims { width, ascent: height / 2.0 + axis, descent: height / 2.0 - axis },
+        cell_style,
+        cell_x,
+        baselines,
+    }
+}
+
+/// Tags set flush right beside an equation: each tag, its x offset and
+/// baseline rise.
+struct TagPlacement<'a> {
+    dims: Dims,
+    tags: Vec<(&'a MathNode, f32, f32)>,
+}
+
+fn place_tags<'a>(font: &MathFont, content: &MathNode, tags: &'a [Option<MathNode>], st: Style) -> TagPlacement<'a> {
+    let size = st.size;
+    let cd = measure(font, content, st);
+    let tag_style = st.switch(MathStyle::Text, &font.constants);
+    // Rows of an alignment carry their own tags, paired up from the top when
+    // there are more or fewer of them than rows; `multline` puts its tag on
+    // the last row and anything else on the equation's baseline.
+    let rows: Vec<(&MathNode, f32)> = match content {
+        MathNode::Aligned { kind, rows } if *kind != AlignKind::Multline && (tags.len() > 1 || rows.len() == tags.len()) => {
+            let p = place_aligned(font, *kind, rows, st);
+            tags.iter()
+                .zip(&p.baselines)
+                .filter_map(|(tag, b)| Some((tag.as_ref()?, cd.ascent - b)))
+                .collect()
+        }
+        MathNode::Aligned { kind: AlignKind::Multline, rows } => {
+            let p = place_aligned(font, AlignKind::Multline, rows, st);
+            let rise = p.baselines.last().map_or(0.0, |b| cd.ascent - b);
+            tags.iter().flatten().take(1).map(|tag| (tag, rise)).collect()
+        }
+        _ => tags.iter().flatten().take(1).map(|tag| (tag, 0.0)).collect(),
+    };
+    let placed: Vec<(&MathNode, Dims, f32)> = rows
+        .into_iter()
+        .map(|(tag, rise)| (tag, measure(font, tag, tag_style), rise))
+        .collect();
+    if placed.is_empty() {
+        return TagPlacement { dims: cd, tags: Vec::new() };
+    }
+
+    let tag_w = placed.iter().fold(0.0f32, |w, (_, d, _)| w.max(d.width));
+    let width = cd.width + TAG_GAP * size + tag_w;
+    let mut dims = Dims { width, ..cd };
+    let tags = placed
+        .into_iter()
+        .map(|(tag, d, rise)| {
+            dims.ascent = dims.ascent.max(rise + d.ascent);
+            dims.descent = dims.descent.max(d.descent - rise);
+            (tag, width - d.width, rise)
+        })
+        .collect();
+    TagPlacement { dims, tags }
+}
+
+fn measure_char(font: &MathFont, ch: char, size: f32) -> Dims {
+    let gid = font.glyph(ch);
     if gid == GlyphId(0) && ch != ' ' {
         return Dims { width: size * 0.6, ascent: size * 0.7, descent: size * 0.2 };
     }
+    let (ascent, descent) = font.ink(gid).map_or((0.0, 0.0), |ink| (ink.ascent, ink.descent));
     Dims {
-        width: sf.h_advance(gid),
-        ascent: sf.ascent(),
-        descent: -sf.descent(),
+        width: font.advance(gid) * size,
+        ascent: ascent * size,
+        descent: descent * size,
     }
 }
 
-fn measure_text(sf: &ab_glyph::PxScaleFont<&FontRef>, text: &str, _size: f32) -> Dims {
-    let mut w = 0.0;
+fn measure_text(font: &MathFont, text: &str, size: f32) -> Dims {
+    let mut d = Dims { width: 0.0, ascent: 0.0, descent: 0.0 };
     for ch in text.chars() {
-        let gid = sf.font().glyph_id(ch);
-        w += sf.h_advance(gid);
+        let cd = measure_char(font, ch, size);
+        d.width += cd.width;
+        d.ascent = d.ascent.max(cd.ascent);
+        d.descent = d.descent.max(cd.descent);
     }
-    Dims { width: w, ascent: sf.ascent(), descent: -sf.descent() }
+    d
 }
 
 // ─── Layout ─────────────────────────────────────────────────────────────────
 
+#[allow(clippy::too_many_arguments)]
 fn layout(
-    font: &FontRef,
-    sf: &ab_glyph::PxScaleFont<&FontRef>,
+    font: &MathFont,
     node: &MathNode,
-    size: f32,
+    st: Style,
     x: f32,
     by: f32, // baseline y
     cmds: &mut Vec<DrawCmd>,
 ) {
+    let c = &font.constants;
+    let size = st.size;
     match node {
         MathNode::Symbol(ch) => {
-            cmds.push(DrawCmd::Glyph { x, y: by, ch: *ch, size });
+            cmds.push(DrawCmd::Glyph { x, y: by, id: font.glyph(styled_char(font, st, *ch)), size, color: None });
         }
         MathNode::Text(t) => {
-            cmds.push(DrawCmd::Text { x, y: by, text: t.clone(), size });
+            cmds.push(DrawCmd::Text { x, y: by, text: t.clone(), size, color: None });
+        }
+        MathNode::Space(_) | MathNode::Kern(_) | MathNode::Phantom { .. } | MathNode::HDotsFor { .. } => {}
+
+        MathNode::Smash { content, .. } => {
+            layout(font, content, st, x, by, cmds);
         }
-        MathNode::Space(_) => {}
 
         MathNode::Row(children) => {
-            let gap = size * 0.12;
             let mut cx = x;
-            for (i, child) in children.iter().enumerate() {
-                if i > 0 {
-                    cx += if is_spaced_node(child) || is_spaced_node(&children[i - 1]) {
-                        size * 0.2
-                    } else {
-                        gap
-                    };
-                }
-                layout(font, sf, child, size, cx, by, cmds);
-                cx += measure(font, sf, child, size).width;
+            for (child, space) in children.iter().zip(row_spacing(children, st)) {
+                cx += space;
+                layout(font, child, st, cx, by, cmds);
+                cx += measure(font, child, st).width;
             }
         }
 
-        MathNode::Frac(num, den) => {
-            let ns = size * 0.8;
-            let nsf = font.as_scaled(PxScale::from(ns));
-            let nd = measure(font, &nsf, num, ns);
-            let dd = measure(font, &nsf, den, ns);
-            let rule_t = size * 0.05;
-            let gap = size * 0.25;
-            let tw = nd.width.max(dd.width) + size * 0.3;
-            // Math axis: slightly above baseline (approx x-height / 2)
-            let axis = by - size * 0.22;
-
-            cmds.push(DrawCmd::HLine { x, y: axis, width: tw, thickness: rule_t });
-
-            let nx = x + (tw - nd.width) / 2.0;
-            let nby = axis - gap - rule_t / 2.0 - nd.descent;
-            layout(font, &nsf, num, ns, nx, nby, cmds);
+        MathNode::Frac { num, den, rule, left, right, num_align } => {
+            let p = place_frac(font, num, den, *rule, *left, *right, st);
+            let nd = measure(font, num, st.num(c));
+            let dd = measure(font, den, st.den(c));
+
+            layout_delim(font, &p.left, size, x, by, cmds);
+            if p.rule > 0.0 {
+                cmds.push(DrawCmd::HLine {
+                    x: x + p.body_x,
+                    y: by - p.axis,
+                    width: p.body_width,
+                    thickness: p.rule,
+                    color: None,
+                });
+            }
 
-            let dx = x + (tw - dd.width) / 2.0;
-            let dby = axis + gap + rule_t / 2.0 + dd.ascent;
-            layout(font, &nsf, den, ns, dx, dby, cmds);
+            let align = match num_align {
+                ColumnAlign::Left => 0.0,
+                ColumnAlign::Center => 0.5,
+                ColumnAlign::Right => 1.0,
+            };
+            let nx = x + p.body_x + (p.body_width - nd.width) * align;
+            layout(font, num, st.num(c), nx, by - p.num_shift, cmds);
+
+            let dx = x + p.body_x + (p.body_width - dd.width) / 2.0;
+            layout(font, den, st.den(c), dx, by + p.den_shift, cmds);
+            layout_delim(font, &p.right, size, x + p.body_x + p.body_width, by, cmds);
         }
 
         MathNode::Sup(base, exp) => {
-            let bd = measure(font, sf, base, size);
-            layout(font, sf, base, size, x, by, cmds);
-            let es = size * 0.65;
-            let esf = font.as_scaled(PxScale::from(es));
-            layout(font, &esf, exp, es, x + bd.width + size * 0.03, by - bd.ascent * 0.5, cmds);
+            let bd = measure(font, base, st);
+            layout(font, base, st, x, by, cmds);
+            let ed = measure(font, exp, st.sup(c));
+            let shift = sup_shift(font, is_char_base(base), bd, ed, st);
+            let ex = x + bd.width + italic_correction(font, base, st);
+            layout(font, exp, st.sup(c), ex, by - shift, cmds);
         }
 
         MathNode::Sub(base, idx) => {
-            let bd = measure(font, sf, base, size);
-            layout(font, sf, base, size, x, by, cmds);
-            let is = size * 0.65;
-            let isf = font.as_scaled(PxScale::from(is));
-            layout(font, &isf, idx, is, x + bd.width + size * 0.03, by + bd.descent + bd.ascent * 0.2, cmds);
+            let bd = measure(font, base, st);
+            layout(font, base, st, x, by, cmds);
+            let id = measure(font, idx, st.sub(c));
+            let shift = sub_shift(font, is_char_base(base), bd, id, st);
+            layout(font, idx, st.sub(c), x + bd.width, by + shift, cmds);
         }
 
         MathNode::SubSup(base, sub, sup) => {
-            let bd = measure(font, sf, base, size);
-            layout(font, sf, base, size, x, by, cmds);
-            let sc = size * 0.65;
-            let ssf = font.as_scaled(PxScale::from(sc));
-            let sx = x + bd.width + size * 0.03;
-            layout(font, &ssf, sup, sc, sx, by - bd.ascent * 0.5, cmds);
-            layout(font, &ssf, sub, sc, sx, by + bd.descent + bd.ascent * 0.2, cmds);
-        }
-
-        MathNode::Sqrt(content) => {
-            let cd = measure(font, sf, content, size);
-            let hook_w = size * 0.15;
-            let diag_w = size * 0.35;
-            let rw = hook_w + diag_w;
-            let bar_gap = size * 0.3;
-            let rule_t = size * 0.05;
-            let stroke = size * 0.06;
-
-            // Key y-coordinates
-            let bar_y = by - cd.ascent - bar_gap;       // overline bar
-            let valley_y = by + cd.descent + size * 0.1; // bottom of V
-            let hook_y = valley_y - (valley_y - bar_y) * 0.25; // hook at ~25% up
-
-            // Hook: short line from top-left down to the valley
-            cmds.push(DrawCmd::Line {
-                x1: x, y1: hook_y,
-                x2: x + hook_w, y2: valley_y,
-                thickness: stroke * 0.7,
-            });
-            // Main diagonal: from valley up to the top-right (meets overline)
-            cmds.push(DrawCmd::Line {
-                x1: x + hook_w, y1: valley_y,
-                x2: x + rw, y2: bar_y,
-                thickness: stroke,
-            });
-            // Overline bar
+            let bd = measure(font, base, st);
+            layout(font, base, st, x, by, cmds);
+            let sp = measure(font, sup, st.sup(c));
+            let sb = measure(font, sub, st.sub(c));
+            let (up, down) = sub_sup_shifts(font, is_char_base(base), bd, sb, sp, st);
+            let sx = x + bd.width;
+            layout(font, sup, st.sup(c), sx + italic_correction(font, base, st), by - up, cmds);
+            layout(font, sub, st.sub(c), sx, by + down, cmds);
+        }
+
+        MathNode::Sqrt { index, radicand } => {
+            let p = place_radical(font, index.as_deref(), radicand, st);
+            draw_vertical(font, &p.glyph, size, x + p.glyph_x, by - p.glyph_bottom, cmds);
             cmds.push(DrawCmd::HLine {
-                x: x + rw, y: bar_y, width: cd.width + size * 0.15, thickness: rule_t,
+                x: x + p.radicand_x,
+                y: by - p.rule_top + p.rule / 2.0,
+                width: p.dims.width - p.radicand_x,
+                thickness: p.rule,
+                color: None,
             });
-            layout(font, sf, content, size, x + rw, by, cmds);
+            layout(font, radicand, st.cramp(), x + p.radicand_x, by, cmds);
+            if let (Some(n), Some((dx, rise))) = (index, p.index) {
+                layout(font, n, st.switch(MathStyle::ScriptScript, c), x + dx, by - rise, cmds);
+            }
         }
 
         MathNode::Overline(content) => {
-            let cd = measure(font, sf, content, size);
+            let cd = measure(font, content, st.cramp());
+            let rule = c.overbar_rule_thickness * size;
+            cmds.push(DrawCmd::HLine {
+                x,
+                y: by - cd.ascent - c.overbar_vertical_gap * size - rule / 2.0,
+                width: cd.width,
+                thickness: rule,
+                color: None,
+            });
+            layout(font, content, st.cramp(), x, by, cmds);
+        }
+
+        MathNode::Underline(content) => {
+            let cd = measure(font, content, st);
+            let rule = c.underbar_rule_thickness * size;
             cmds.push(DrawCmd::HLine {
-                x, y: by - cd.ascent - size * 0.1, width: cd.width, thickness: size * 0.05,
+                x,
+                y: by + cd.descent + c.underbar_vertical_gap * size + rule / 2.0,
+                width: cd.width,
+                thickness: rule,
+                color: None,
             });
-            layout(font, sf, content, size, x, by, cmds);
+            layout(font, content, st, x, by, cmds);
+        }
+
+        MathNode::WideAccent(ach, content) => {
+            let p = place_wide_accent(font, *ach, content, st);
+            draw_horizontal(&p.glyph, size, x + p.glyph_x, by - p.rise, cmds);
+            layout(font, content, st.cramp(), x + p.content_x, by, cmds);
+        }
+
+        MathNode::Brace { over, content } => {
+            let p = place_brace(font, *over, content, st);
+            draw_horizontal(&p.glyph, size, x + p.glyph_x, by - p.rise, cmds);
+            layout(font, content, st, x + p.content_x, by, cmds);
         }
 
         MathNode::Accent(ach, content) => {
-            let cd = measure(font, sf, content, size);
-            layout(font, sf, content, size, x, by, cmds);
-            let as_ = size * 0.5;
-            let asf = font.as_scaled(PxScale::from(as_));
-            let agid = font.glyph_id(*ach);
-            let aw = asf.h_advance(agid);
+            let cd = measure(font, content, st.cramp());
+            layout(font, content, st.cramp(), x, by, cmds);
+            let base_attach = match content.as_ref() {
+                MathNode::Symbol(ch) => {
+                    font.top_accent_attachment(font.glyph(styled_char(font, st, *ch))) * size
+                }
+                _ => cd.width / 2.0,
+            };
+            let accent_attach = font.top_accent_attachment(font.glyph(*ach)) * size;
             cmds.push(DrawCmd::Glyph {
-                x: x + (cd.width - aw) / 2.0,
-                y: by - cd.ascent - size * 0.05,
-                ch: *ach, size: as_,
+                x: x + base_attach - accent_attach,
+                y: by - accent_shift(font, cd, size),
+                id: font.glyph(*ach),
+                size,
+                color: None,
             });
         }
 
-        MathNode::Matrix { rows, left_delim, right_delim } => {
-            layout_matrix(font, sf, rows, *left_delim, *right_delim, size, x, by, cmds);
+        MathNode::Matrix { rows, left_delim, right_delim, spec } => {
+            let p = place_matrix(font, rows, *left_delim, *right_delim, spec, st.cells(c));
+            layout_matrix(font, rows, &p, x, by, cmds);
         }
 
-        MathNode::Cases(rows) => {
-            layout_matrix(font, sf, rows, Some('{'), None, size, x, by, cmds);
+        MathNode::Cases { rows, display, right_brace } => {
+            let spec = cases_spec();
+            let p = place_cases(font, rows, *display, *right_brace, &spec, st);
+            layout_matrix(font, rows, &p, x, by, cmds);
         }
 
-        MathNode::Delimited { left, right, content } => {
-            let cd = measure(font, sf, content, size);
-            let ds = (cd.height() + size * 0.2).min(size * 2.5);
-            let dw = ds * 0.3;
-            let inner_pad = size * 0.12;
-            if *left != '\0' {
-                cmds.push(DrawCmd::Glyph { x, y: by, ch: *left, size: ds });
+        MathNode::Tagged { content, tags } => {
+            let p = place_tags(font, content, tags, st);
+            layout(font, content, st, x, by, cmds);
+            for (tag, tx, rise) in p.tags {
+                layout(font, tag, st.switch(MathStyle::Text, c), x + tx, by - rise, cmds);
             }
-            layout(font, sf, content, size, x + dw + inner_pad, by, cmds);
-            if *right != '\0' {
-                cmds.push(DrawCmd::Glyph {
-                    x: x + dw + inner_pad + cd.width + inner_pad,
-                    y: by, ch: *right, size: ds,
-                });
+        }
+
+        MathNode::Aligned { kind, rows } => {
+            let p = place_aligned(font, *kind, rows, st);
+            let top = by - p.dims.ascent;
+            for (row, (xs, baseline)) in rows.iter().zip(p.cell_x.iter().zip(&p.baselines)) {
+                for (cell, cx) in row.iter().zip(xs) {
+                    layout(font, cell, p.cell_style, x + cx, top + baseline, cmds);
+                }
             }
         }
-    }
-}
 
-fn layout_matrix(
-    font: &FontRef,
-    sf: &ab_glyph::PxScaleFont<&FontRef>,
-    rows: &[Vec<MathNode>],
-    left: Option<char>,
-    right: Option<char>,
-    size: f32,
-    x: f32,
-    by: f32,
-    cmds: &mut Vec<DrawCmd>,
-) {
-    if rows.is_empty() { return; }
-    let ncols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
-    let gap_x = size * 0.6;
-    let gap_y = size * 0.3;
-    let dw = size * 0.3;
+        MathNode::BigOp { op, limits, sub, sup } => {
+            let p = place_big_op(font, op, *limits, sub.as_deref(), sup.as_deref(), st);
+            match p.glyph {
+                Some((id, drop)) => cmds.push(DrawCmd::Glyph { x: x + p.op_x, y: by + drop, id, size, color: None }),
+                None => layout(font, op, st, x + p.op_x, by, cmds),
+            }
+            if let (Some(n), Some((dx, rise))) = (sup, p.sup) {
+                layout(font, n, st.sup(c), x + dx, by - rise, cmds);
+            }
+            if let (Some(n), Some((dx, drop))) = (sub, p.sub) {
+                layout(font, n, st.sub(c), x + dx, by + drop, cmds);
+            }
+        }
 
-    let mut col_w = vec![0.0f32; ncols];
-    let mut row_m: Vec<(f32, f32)> = Vec::new();
+        MathNode::Style(level, content) => {
+            layout(font, content, st.switch(*level, c), x, by, cmds);
+        }
+
+        MathNode::Styled(variant, content) => {
+            layout(font, content, st.styled(*variant), x, by, cmds);
+        }
+
+        MathNode::Class(_, content) => {
+            layout(font, content, st, x, by, cmds);
+        }
 
-    for row in rows {
-        let mut ra = size * 0.4;
-        let mut rd = size * 0.2;
-        for (j, cell) in row.iter().enumerate() {
-            let d = measure(font, sf, cell, size);
-            if j < ncols { col_w[j] = col_w[j].max(d.width); }
-            ra = ra.max(d.ascent);
-            rd = rd.max(d.descent);
+        MathNode::Negated(content) => {
+            let cd = measure(font, content, st);
+            layout(font, content, st, x, by, cmds);
+            // Centre the slash's ink on the content, whatever its side bearings.
+            let slash = font.glyph('\u{0338}');
+            let mid = font.ink(slash).map_or(0.0, |ink| (ink.x_min + ink.x_max) / 2.0);
+            cmds.push(DrawCmd::Glyph { x: x + cd.width / 2.0 - mid * size, y: by, id: slash, size, color: None });
+        }
+
+        MathNode::XArrow { arrow, above, below } => {
+            let p = place_xarrow(font, *arrow, above, below.as_deref(), st);
+            match &p.glyph {
+                Some((glyph, gx)) => draw_horizontal(glyph, size, x + gx, by, cmds),
+                None => layout_line_arrow(font, *arrow, p.dims.width, size, x, by, cmds),
+            }
+            layout(font, above, st.sup(c), x + p.above.0, by - p.above.1, cmds);
+            if let (Some(below), Some((bx, drop))) = (below, p.below) {
+                layout(font, below, st.sub(c), x + bx, by + drop, cmds);
+            }
+        }
+
+        MathNode::Cancel { content, kind } => {
+            let p = place_cancel(font, content, kind, st);
+            layout(font, content, st, x, by, cmds);
+            let thickness = CANCEL_RULE * size;
+            for ((x1, y1), (x2, y2)) in p.strokes {
+                cmds.push(DrawCmd::Line { x1: x + x1, y1: by + y1, x2: x + x2, y2: by + y2, thickness, color: None });
+            }
+            if let Some((value, vx, vy)) = p.value {
+                layout(font, value, st.sup(c), x + vx, by + vy, cmds);
+            }
+        }
+
+        MathNode::Colored(color, content) => {
+            let start = cmds.len();
+            layout(font, content, st, x, by, cmds);
+            // Nested colors were applied first and win.
+            for cmd in &mut cmds[start..] {
+                cmd.color_mut().get_or_insert(rgba(*color));
+            }
+        }
+
+        MathNode::Boxed { content, frame, frame_color, background } => {
+            let d = measure(font, node, st);
+            let pad = box_padding(*frame, size);
+            let (top, height) = (by - d.ascent, d.height());
+            if let Some(bg) = background {
+                cmds.push(DrawCmd::Rect { x, y: top, width: d.width, height, color: Some(rgba(*bg)) });
+            }
+            layout(font, content, st, x + pad, by, cmds);
+            if *frame {
+                let thickness = FBOX_RULE * size;
+                let color = frame_color.map(rgba);
+                cmds.push(DrawCmd::Frame { x, y: top, width: d.width, height, thickness, color });
+            }
+        }
+
+        MathNode::Delimited { left, right, content, middle } => {
+            let p = place_fence(font, *left, content, middle, *right, st);
+            for (delim, dx) in &p.delims {
+                layout_delim(font, delim, size, x + dx, by, cmds);
+            }
+            for (segment, sx) in p.segments {
+                layout(font, segment, st, x + sx, by, cmds);
+            }
+        }
+        MathNode::BigDelim { delim, size: level } => {
+            layout_delim(font, &big_delim(font, *delim, *level, size), size, x, by, cmds);
         }
-        row_m.push((ra, rd));
     }
+}
 
-    let th: f32 = row_m.iter().map(|(a, d)| a + d).sum::<f32>()
-        + gap_y * rows.len().saturating_sub(1) as f32;
+/// Space between a box's edge and its content: \fboxsep, plus the rule of a frame.
+fn box_padding(frame: bool, size: f32) -> f32 {
+    (FBOX_SEP + if frame { FBOX_RULE } else { 0.0 }) * size
+}
 
-    let mut cx = x;
-    if let Some(ld) = left {
-        let ds = th.min(size * 3.0);
-        cmds.push(DrawCmd::Glyph { x: cx, y: by, ch: ld, size: ds });
-        cx += dw;
+fn rgba(color: Color) -> [u8; 4] {
+    let [r, g, b] = color.0;
+    [r, g, b, 255]
+}
+
+fn layout_delim(font: &MathFont, delim: &Delim, size: f32, x: f32, by: f32, cmds: &mut Vec<DrawCmd>) {
+    if let Some(glyph) = &delim.glyph {
+        draw_vertical(font, glyph, size, x, by + delim.descent, cmds);
     }
-    cx += size * 0.1;
+}
 
-    let top = by - th / 2.0;
-    let mut cy = top;
+/// Draw a vertically stretched glyph with the bottom of its ink at `bottom`.
+fn draw_vertical(font: &MathFont, glyph: &Stretched, size: f32, x: f32, bottom: f32, cmds: &mut Vec<DrawCmd>) {
+    match glyph {
+        Stretched::Variant(gid) => {
+            let descent = font.ink(*gid).map_or(0.0, |ink| ink.descent);
+            cmds.push(DrawCmd::Glyph { x, y: bottom - descent * size, id: *gid, size, color: None });
+        }
+        Stretched::Assembly { parts, .. } => {
+            // Parts are listed bottom-up; stack each part's ink from the bottom.
+            for part in parts {
+                let descent = font.ink(part.glyph).map_or(0.0, |ink| ink.descent);
+                let part_bottom = bottom - part.offset * size;
+                cmds.push(DrawCmd::Glyph { x, y: part_bottom - descent * size, id: part.glyph, size, color: None });
+            }
+        }
+    }
+}
+
+/// Draw a horizontally stretched glyph with its origin at (`x`, `y`).
+fn draw_horizontal(glyph: &Stretched, size: f32, x: f32, y: f32, cmds: &mut Vec<DrawCmd>) {
+    match glyph {
+        Stretched::Variant(gid) => cmds.push(DrawCmd::Glyph { x, y, id: *gid, size, color: None }),
+        Stretched::Assembly { parts, .. } => {
+            for part in parts {
+                cmds.push(DrawCmd::Glyph { x: x + part.offset * size, y, id: part.glyph, size, color: None });
+            }
+        }
+    }
+}
 
+fn layout_matrix(font: &MathFont, rows: &[Vec<MathNode>], p: &MatrixPlacement, x: f32, by: f32, cmds: &mut Vec<DrawCmd>) {
+    let size = p.cell_style.size;
+    if let Some((ld, rd)) = &p.fence {
+        layout_delim(font, ld, size, x, by, cmds);
+        layout_delim(font, rd, size, x + ld.width + p.body.width, by, cmds);
+    }
+    let top = by - p.body.ascent;
     for (i, row) in rows.iter().enumerate() {
-        let (ra, rd) = row_m[i];
-        let cell_by = cy + ra;
-        let mut cell_x = cx;
-        for (j, cell) in row.iter().enumerate() {
-            let d = measure(font, sf, cell, size);
-            let off = (col_w[j] - d.width) / 2.0;
-            layout(font, sf, cell, size, cell_x + off, cell_by, cmds);
-            cell_x += col_w[j] + gap_x;
+        let baseline = top + p.baselines[i];
+        for ((cell, cx), w) in row.iter().zip(&p.cell_x[i]).zip(&p.cell_w[i]) {
+            match cell {
+                MathNode::HDotsFor { spacing, .. } => {
+                    layout_dot_leaders(font, *spacing, p.cell_style, x + cx, baseline, *w, cmds);
+                }
+                MathNode::Symbol(ch @ ('\u{22EE}' | '\u{22F1}' | '\u{22F0}')) => {
+                    layout_row_dots(font, *ch, p.cell_style.size, x + cx, baseline, p.extents[i], cmds);
+                }
+                _ => layout(font, cell, p.cell_style, x + cx, baseline, cmds),
+            }
+        }
+        for (sep, sx) in &p.separators {
+            layout(font, sep, p.cell_style, x + sx, baseline, cmds);
         }
-        cy += ra + rd + gap_y;
     }
+    let thickness = ARRAY_RULE_WIDTH * size;
+    for vx in &p.vrules {
+        cmds.push(DrawCmd::Line {
+            x1: x + vx,
+            y1: top,
+            x2: x + vx,
+            y2: by + p.body.descent,
+            thickness,
+            color: None,
+        });
+    }
+    for (hx, width, hy) in &p.hrules {
+        cmds.push(DrawCmd::HLine { x: x + hx, y: top + hy, width: *width, thickness, color: None });
+    }
+}
 
-    if let Some(rd) = right {
-        let content_w: f32 = col_w.iter().sum::<f32>() + gap_x * ncols.saturating_sub(1) as f32;
-        let ds = th.min(size * 3.0);
-        cmds.push(DrawCmd::Glyph { x: cx + content_w + size * 0.1, y: by, ch: rd, size: ds });
+/// `\hdotsfor`: dots spaced by `spacing` × 3mu, centred across `width`.
+fn layout_dot_leaders(font: &MathFont, spacing: f32, st: Style, x: f32, by: f32, width: f32, cmds: &mut Vec<DrawCmd>) {
+    let size = st.size;
+    let dot = font.glyph('.');
+    let advance = font.advance(dot) * size;
+    let pitch = advance + spacing * 3.0 / 18.0 * size;
+    let count = (width / pitch).floor().max(0.0);
+    let start = x + (width - count * pitch) / 2.0 + (pitch - advance) / 2.0;
+    for k in 0..count as usize {
+        cmds.push(DrawCmd::Glyph { x: start + k as f32 * pitch, y: by, id: dot, size, color: None });
+    }
+}
+
+/// `\vdots`, `\ddots` or `\iddots` alone in a cell: three dots spread over
+/// the row's full height, across the glyph's own width.
+fn layout_row_dots(font: &MathFont, ch: char, size: f32, x: f32, by: f32, extent: (f32, f32), cmds: &mut Vec<DrawCmd>) {
+    let dot = font.glyph('.');
+    let (Some(dot_ink), Some(ink)) = (font.ink(dot), font.ink(font.glyph(ch))) else {
+        return;
+    };
+    let (ra, rd) = extent;
+    let radius = (dot_ink.x_max - dot_ink.x_min) / 2.0 * size;
+    let dot_x = (dot_ink.x_min + dot_ink.x_max) / 2.0 * size;
+    let dot_y = (dot_ink.ascent - dot_ink.descent) / 2.0 * size;
+    let (left, right) = (x + ink.x_min * size + radius, x + ink.x_max * size - radius);
+    for k in 0..3 {
+        let t = k as f32 / 2.0;
+        let cx = match ch {
+            '\u{22EE}' => (left + right) / 2.0,
+            '\u{22F1}' => left + (right - left) * t,
+            _ => right - (right - left) * t,
+        };
+        let cy = by - ra + (ra + rd) * (k as f32 + 0.5) / 3.0;
+        cmds.push(DrawCmd::Glyph { x: cx - dot_x, y: cy + dot_y, id: dot, size, color: None });
     }
 }
 
 // ─── Drawing primitives ─────────────────────────────────────────────────────
 
-fn draw_char(font: &FontRef, img: &mut ImageBuf, ch: char, x: f32, y: f32, size: f32, color: [u8; 4]) {
-    let gid = font.glyph_id(ch);
-    if gid == GlyphId(0) && ch != ' ' {
+fn draw_glyph(font: &FontRef, img: &mut ImageBuf, gid: GlyphId, x: f32, y: f32, size: f32, color: [u8; 4]) {
+    if gid == GlyphId(0) {
         draw_placeholder(img, x, y, size, color);
         return;
     }
@@ -642,8 +2195,8 @@ fn draw_text_str(font: &FontRef, img: &mut ImageBuf, text: &str, x: f32, y: f32,
     let sf = font.as_scaled(PxScale::from(size));
     let mut cx = x;
     for ch in text.chars() {
-        draw_char(font, img, ch, cx, y, size, color);
         let gid = font.glyph_id(ch);
+        draw_glyph(font, img, gid, cx, y, size, color);
         cx += sf.h_advance(gid);
     }
 }
@@ -687,7 +2240,7 @@ fn draw_line(img: &mut ImageBuf, x1: f32, y1: f32, x2: f32, y2: f32, thickness:
             // Inside the line segment (with small extension at ends)?
             if along >= -0.5 && along <= len + 0.5 && perp <= thickness / 2.0 + 0.5 {
                 // Anti-alias based on perpendicular distance
-                let edge = (thickness / 2.0 + 0.5 - perp).min(1.0).max(0.0);
+                let edge = (thickness / 2.0 + 0.5 - perp).clamp(0.0, 1.0);
                 let alpha = (edge * 255.0) as u8;
                 img.put_pixel(px as u32, py as u32, color, alpha);
             }
@@ -0,0 +1,164 @@
+/// SVG backend: turns the shared `DrawCmd` list into a standalone SVG document.
+///
+/// Glyphs are written as outline paths taken from the embedded font, so the
+/// output does not depend on any font being installed where it is viewed.
+use ab_glyph::{Font, FontRef, GlyphId, OutlineCurve, PxScale, ScaleFont};
+use std::fmt::Write;
+
+use crate::render::{DrawCmd, Scene, Theme};
+
+pub(crate) fn write_svg(font: &FontRef, scene: &Scene, theme: &Theme, scale: f32) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        num(scene.width as f32 / scale),
+        num(scene.height as f32 / scale),
+        scene.width,
+        scene.height,
+    );
+    let _ = writeln!(
+        out,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(theme.bg())
+    );
+    let fg = hex(theme.fg());
+    let _ = writeln!(out, r#"<g fill="{}" stroke="none">"#, fg);
+
+    for cmd in &scene.cmds {
+        // Commands in a color of their own get a group overriding the fill.
+        let own = cmd.color();
+        let color = own.map_or_else(|| fg.clone(), hex);
+        if own.is_some() {
+            let _ = writeln!(out, r#"<g fill="{}">"#, color);
+        }
+        match cmd {
+            DrawCmd::Glyph { x, y, id, size, .. } => {
+                glyph_path(font, &mut out, *id, *x, *y, *size, &color);
+            }
+            DrawCmd::HLine { x, y, width, thickness, .. } => {
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+                    num(*x),
+                    num(y - thickness / 2.0),
+                    num(*width),
+                    num(*thickness),
+                );
+            }
+            DrawCmd::Line { x1, y1, x2, y2, thickness, .. } => {
+                let _ = writeln!(
+                    out,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+                    num(*x1),
+                    num(*y1),
+                    num(*x2),
+                    num(*y2),
+                    color,
+                    num(*thickness),
+                );
+            }
+            DrawCmd::Text { x, y, text, size, .. } => {
+                let sf = font.as_scaled(PxScale::from(*size));
+                let mut cx = *x;
+                for ch in text.chars() {
+                    glyph_path(font, &mut out, font.glyph_id(ch), cx, *y, *size, &color);
+                    cx += sf.h_advance(font.glyph_id(ch));
+                }
+            }
+            DrawCmd::Rect { x, y, width, height, .. } => {
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+                    num(*x),
+                    num(*y),
+                    num(*width),
+                    num(*height),
+                );
+            }
+            DrawCmd::Frame { x, y, width, height, thickness, .. } => {
+                // The stroke is centred on the outline, so inset it to stay inside the bounds.
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+                    num(x + thickness / 2.0),
+                    num(y + thickness / 2.0),
+                    num(width - thickness),
+                    num(height - thickness),
+                    color,
+                    num(*thickness),
+                );
+            }
+        }
+        if own.is_some() {
+            out.push_str("</g>\n");
+        }
+    }
+
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+/// Append one glyph as a `<path>`, positioned with its origin on the baseline at (x, y).
+fn glyph_path(font: &FontRef, out: &mut String, gid: GlyphId, x: f32, y: f32, size: f32, fg: &str) {
+    if gid == GlyphId(0) {
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-opacity="0.5"/>"#,
+            num(x),
+            num(y - size * 0.5),
+            num(size * 0.5),
+            num(size * 0.6),
+            fg,
+        );
+        return;
+    }
+    let Some(outline) = font.outline(gid) else {
+        return;
+    };
+    let s = font.as_scaled(PxScale::from(size)).h_scale_factor();
+    let pt = |p: ab_glyph::Point| format!("{} {}", num(x + p.x * s), num(y - p.y * s));
+
+    let mut d = String::new();
+    let mut last: Option<ab_glyph::Point> = None;
+    for curve in &outline.curves {
+        let (start, end) = match curve {
+            OutlineCurve::Line(a, b) => (*a, *b),
+            OutlineCurve::Quad(a, _, b) => (*a, *b),
+            OutlineCurve::Cubic(a, _, _, b) => (*a, *b),
+        };
+        if last != Some(start) {
+            if last.is_some() {
+                d.push('Z');
+            }
+            let _ = write!(d, "M{}", pt(start));
+        }
+        match curve {
+            OutlineCurve::Line(_, b) => {
+                let _ = write!(d, "L{}", pt(*b));
+            }
+            OutlineCurve::Quad(_, c, b) => {
+                let _ = write!(d, "Q{} {}", pt(*c), pt(*b));
+            }
+            OutlineCurve::Cubic(_, c1, c2, b) => {
+                let _ = write!(d, "C{} {} {}", pt(*c1), pt(*c2), pt(*b));
+            }
+        }
+        last = Some(end);
+    }
+    if !d.is_empty() {
+        d.push('Z');
+        let _ = writeln!(out, r#"<path d="{}"/>"#, d);
+    }
+}
+
+fn hex(c: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+/// Format a coordinate with at most two decimals and no trailing zeros.
+fn num(v: f32) -> String {
+    let s = format!("{:.2}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A fresh scratch directory for one test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("math2img-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn strict_skips_equations_with_errors_and_fails() {
+    let dir = scratch_dir("strict");
+    let input = dir.join("doc.tex");
+    std::fs::write(&input, "$$a + b$$\n\n$$x^a^b$$\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_math2img"))
+        .arg(&input)
+        .arg("-o")
+        .arg(&dir)
+        .arg("--strict")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("[ERROR] equation 2 at line 3, column 6"), "{}", stderr);
+    assert!(dir.join("equation_0001.png").exists());
+    assert!(!dir.join("equation_0002.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn without_strict_errors_are_warnings() {
+    let dir = scratch_dir("lenient");
+    let input = dir.join("doc.tex");
+    std::fs::write(&input, "$$x^a^b$$\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_math2img"))
+        .arg(&input)
+        .arg("-o")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("[WARN] equation 1 at line 1, column 6"), "{}", stderr);
+    assert!(dir.join("equation_0001.png").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}